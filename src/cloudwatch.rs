@@ -0,0 +1,60 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use error::LogError;
+
+/// Envelope a CloudWatch Logs subscription filter writes into each Firehose record.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LogsData {
+    pub message_type: String,
+    pub log_group: String,
+    pub log_stream: String,
+    pub log_events: Vec<LogEvent>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LogEvent {
+    pub timestamp: i64,
+    pub message: String,
+}
+
+impl LogsData {
+    /// Subscription filters send one of these when they are created, to check the destination.
+    pub fn is_control_message(&self) -> bool {
+        self.message_type == "CONTROL_MESSAGE"
+    }
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+pub fn decode(data: &[u8]) -> Result<LogsData, LogError> {
+    let mut buf = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut buf)?;
+    serde_json::from_slice(&buf).map_err(|e| LogError::JsonError(e))
+}
+
+#[cfg(test)]
+pub fn encode(json: &str) -> Vec<u8> {
+    use std::io::Write;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    let mut e = GzEncoder::new(Vec::new(), Compression::default());
+    e.write_all(json.as_bytes()).unwrap();
+    e.finish().unwrap()
+}
+
+#[test]
+fn decode_test() {
+    let data = encode(r#"{"messageType":"CONTROL_MESSAGE","owner":"CloudwatchLogs","logGroup":"","logStream":"","subscriptionFilters":[],"logEvents":[{"id":"","timestamp":1513257405000,"message":"CWL CONTROL MESSAGE: Checking health of destination Firehose."}]}"#);
+    assert!(is_gzip(&data));
+    let logs = decode(&data).unwrap();
+    assert!(logs.is_control_message());
+    assert_eq!(logs.log_events.len(), 1);
+
+    assert!(!is_gzip(b"7.248.7.119 - -"));
+}
@@ -0,0 +1,139 @@
+use regex::Regex;
+
+use error::LogError;
+use parser::{self, LogParser, ApacheParser};
+use partition::{self, PartitionKey};
+
+/// Transformation status reported back to Firehose for each record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordResult {
+    Ok,
+    Dropped,
+    ProcessingFailed,
+}
+
+impl RecordResult {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            RecordResult::Ok => "Ok",
+            RecordResult::Dropped => "Dropped",
+            RecordResult::ProcessingFailed => "ProcessingFailed",
+        }
+    }
+}
+
+impl std::str::FromStr for RecordResult {
+    type Err = LogError;
+
+    fn from_str(s: &str) -> Result<RecordResult, LogError> {
+        match s {
+            "Ok" => Ok(RecordResult::Ok),
+            "Dropped" => Ok(RecordResult::Dropped),
+            "ProcessingFailed" => Ok(RecordResult::ProcessingFailed),
+            _ => Err(LogError::ConfigError(format!("unknown record result: {}", s))),
+        }
+    }
+}
+
+/// Settings read from the Lambda environment at cold start.
+///
+/// * `LOG_FORMAT` - name of the parser to use (default `apache`), see `parser::from_env`.
+/// * `ON_PARSE_ERROR` - what to do with lines that fail to parse (default `ProcessingFailed`).
+///   `ProcessingFailed` fails the whole record, even when its other lines parsed. `Ok`
+///   writes each failed line through unchanged among the parsed ones, and `Dropped`
+///   leaves it out; a record left with nothing to write is `Dropped`. A record that can't
+///   be read at all, such as invalid UTF-8, is passed through followed by
+///   `RECORD_DELIMITER` under `Ok`.
+/// * `DROP_PATTERN` - raw lines matching this regex are skipped; a record is `Dropped` only
+///   when none of its lines are left.
+/// * `RECORD_DELIMITER` - written after every JSON object: `newline` (the default), `none`,
+///   or any other string, where `\n`, `\r`, `\t` and `\\` are unescaped.
+/// * `PARTITION_KEYS` - dynamic partitioning keys, see `partition::parse_partition_keys`.
+pub struct Config {
+    pub parser: Box<dyn LogParser>,
+    pub on_parse_error: RecordResult,
+    pub drop_pattern: Option<Regex>,
+    pub record_delimiter: String,
+    pub partition_keys: Vec<PartitionKey>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            parser: Box::new(ApacheParser::default()),
+            on_parse_error: RecordResult::ProcessingFailed,
+            drop_pattern: None,
+            record_delimiter: "\n".to_owned(),
+            partition_keys: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config, LogError> {
+        let mut config = Config::default();
+        config.parser = parser::from_env()?;
+        if let Ok(s) = std::env::var("ON_PARSE_ERROR") {
+            config.on_parse_error = s.parse().map_err(|e| env_error("ON_PARSE_ERROR", e))?;
+        }
+        if let Ok(s) = std::env::var("DROP_PATTERN") {
+            let re = Regex::new(&s)
+                .map_err(|e| LogError::ConfigError(format!("invalid DROP_PATTERN: {}", e)))?;
+            config.drop_pattern = Some(re);
+        }
+        if let Ok(s) = std::env::var("RECORD_DELIMITER") {
+            config.record_delimiter = parse_delimiter(&s);
+        }
+        if let Ok(s) = std::env::var("PARTITION_KEYS") {
+            config.partition_keys = partition::parse_partition_keys(&s)
+                .map_err(|e| env_error("PARTITION_KEYS", e))?;
+        }
+        Ok(config)
+    }
+}
+
+/// Names the environment variable a configuration error came from, so a failed cold
+/// start can be diagnosed from its log line alone.
+fn env_error(name: &str, e: LogError) -> LogError {
+    LogError::ConfigError(format!("{}: {}", name, e))
+}
+
+fn parse_delimiter(s: &str) -> String {
+    match s {
+        "newline" => "\n".to_owned(),
+        "none" => String::new(),
+        _ => {
+            let mut out = String::new();
+            let mut chars = s.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    out.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some(c) => out.push(c),
+                    None => out.push('\\'),
+                }
+            }
+            out
+        }
+    }
+}
+
+#[test]
+fn record_result_test() {
+    assert_eq!("Dropped".parse::<RecordResult>().unwrap(), RecordResult::Dropped);
+    assert_eq!(RecordResult::ProcessingFailed.as_str(), "ProcessingFailed");
+    assert!("Failed".parse::<RecordResult>().is_err());
+}
+
+#[test]
+fn parse_delimiter_test() {
+    assert_eq!(parse_delimiter("newline"), "\n");
+    assert_eq!(parse_delimiter("none"), "");
+    assert_eq!(parse_delimiter("\\r\\n"), "\r\n");
+    assert_eq!(parse_delimiter("|"), "|");
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum LogError {
+    RegexParseError,
+    UTF8Error(std::string::FromUtf8Error),
+    DateTimeParseError(chrono::ParseError),
+    IntError(std::num::ParseIntError),
+    FloatError(std::num::ParseFloatError),
+    JsonError(serde_json::Error),
+    ConfigError(String),
+    HostParseError(String),
+    IoError(std::io::Error),
+    PartitionKeyError(String),
+    TimestampRangeError(i64),
+    /// Columns expected by the schema, and fields found.
+    ColumnCountError(usize, usize),
+    /// Column, and the value that isn't a boolean.
+    BoolParseError(String, String),
+    /// Partition keys whose values differ between the lines of one record.
+    PartitionKeyConflict(Vec<String>),
+}
+
+impl From<std::string::FromUtf8Error> for LogError {
+    fn from(err: std::string::FromUtf8Error) -> LogError {
+        LogError::UTF8Error(err)
+    }
+}
+
+impl From<chrono::ParseError> for LogError {
+    fn from(err: chrono::ParseError) -> LogError {
+        LogError::DateTimeParseError(err)
+    }
+}
+
+impl From<std::num::ParseIntError> for LogError {
+    fn from(err: std::num::ParseIntError) -> LogError {
+        LogError::IntError(err)
+    }
+}
+
+impl From<std::num::ParseFloatError> for LogError {
+    fn from(err: std::num::ParseFloatError) -> LogError {
+        LogError::FloatError(err)
+    }
+}
+
+impl From<std::io::Error> for LogError {
+    fn from(err: std::io::Error) -> LogError {
+        LogError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for LogError {
+    fn from(err: serde_json::Error) -> LogError {
+        LogError::JsonError(err)
+    }
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            LogError::RegexParseError => f.write_str("FAIL. unmatched pattern."),
+            LogError::UTF8Error(ref err) => fmt::Display::fmt(err, f),
+            LogError::DateTimeParseError(ref err) => fmt::Display::fmt(err, f),
+            LogError::IntError(ref err) => fmt::Display::fmt(err, f),
+            LogError::FloatError(ref err) => fmt::Display::fmt(err, f),
+            LogError::JsonError(ref err) => fmt::Display::fmt(err, f),
+            LogError::ConfigError(ref msg) => f.write_str(msg),
+            LogError::HostParseError(ref host) => write!(f, "invalid host: {}", host),
+            LogError::IoError(ref err) => fmt::Display::fmt(err, f),
+            LogError::PartitionKeyError(ref field) => write!(f, "no partition key value in field: {}", field),
+            LogError::TimestampRangeError(secs) => write!(f, "timestamp out of range: {}", secs),
+            LogError::ColumnCountError(expected, found) => write!(f, "expected {} columns, found {}", expected, found),
+            LogError::BoolParseError(ref column, ref value) => write!(f, "invalid bool in column {}: {}", column, value),
+            LogError::PartitionKeyConflict(ref keys) => write!(f, "lines disagree on partition keys: {}", keys.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for LogError {
+    fn description(&self) -> &str {
+        match *self {
+            LogError::RegexParseError => "FAIL. unmatched pattern.",
+            LogError::UTF8Error(ref err) => err.description(),
+            LogError::DateTimeParseError(ref err) => err.description(),
+            LogError::IntError(ref err) => err.description(),
+            LogError::FloatError(ref err) => err.description(),
+            LogError::JsonError(ref err) => err.description(),
+            LogError::ConfigError(ref msg) => msg,
+            LogError::HostParseError(_) => "FAIL. invalid host.",
+            LogError::IoError(ref err) => err.description(),
+            LogError::PartitionKeyError(_) => "FAIL. no partition key value.",
+            LogError::TimestampRangeError(_) => "FAIL. timestamp out of range.",
+            LogError::ColumnCountError(..) => "FAIL. column count mismatch.",
+            LogError::BoolParseError(..) => "FAIL. invalid bool.",
+            LogError::PartitionKeyConflict(_) => "FAIL. lines disagree on partition keys.",
+        }
+    }
+}
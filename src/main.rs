@@ -6,160 +6,317 @@ extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
 
-#[macro_use]
-extern crate lazy_static;
 extern crate regex;
 extern crate rayon;
+extern crate flate2;
+
+mod cloudwatch;
+mod config;
+mod error;
+mod parser;
+mod partition;
+mod response;
 
-use std::fmt;
 use chrono::prelude::*;
-use regex::Regex;
 use rayon::prelude::*;
+use serde_json::Value;
 
 use lambda::event::Base64Data;
-use lambda::event::firehose::{KinesisFirehoseEvent, KinesisFirehoseEventRecord, KinesisFirehoseResponse, KinesisFirehoseResponseRecord};
+use lambda::event::firehose::{KinesisFirehoseEvent, KinesisFirehoseEventRecord, KinesisFirehoseResponseRecord};
 
-lazy_static! {
-    static ref RE: Regex = Regex::new(r#"^([\d.]+) (\S+) (\S+) \[([\w:/]+\s[\+\-]\d{2}:?\d{2}){0,1}\] "(.+?)" (\d{3}) (\d+)"#).unwrap();
-}
+use config::{Config, RecordResult};
+use error::LogError;
+use parser::RecordContext;
+use response::{FirehoseResponse, FirehoseResponseRecord};
 
 fn main() {
-    lambda::start(|input: KinesisFirehoseEvent| {
-        Ok(my_handler(input))
+    let config = Config::from_env().unwrap_or_else(|e| {
+        eprintln!("invalid configuration: {}", e);
+        std::process::exit(1)
+    });
+    lambda::start(move |input: KinesisFirehoseEvent| {
+        Ok(my_handler(&config, input))
     })
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct AccessLog {
-    host: String,
-    ident: String,
-    authuser: String,
-    #[serde(rename = "@timestamp")]
-    timestamp: String,
-    #[serde(rename = "@timestamp_utc")]
-    timestamp_utc: String,
-    request: String,
-    response: u32,
-    bytes: u32,
+/// Parsed lines of one Firehose record, and the lines that failed to parse.
+#[derive(Debug, Default)]
+struct Transformed {
+    lines: Vec<Value>,
+    errors: Vec<LineError>,
 }
 
+/// A line that failed to parse, with the number of lines parsed before it.
 #[derive(Debug)]
-enum LogError {
-    RegexParseError,
-    UTF8Error(std::string::FromUtf8Error),
-    DateTimeParseError(chrono::ParseError),
-    IntError(std::num::ParseIntError),
-    JsonError(serde_json::Error)
+struct LineError {
+    at: usize,
+    raw: String,
+    error: LogError,
 }
 
-impl From<std::string::FromUtf8Error> for LogError {
-    fn from(err: std::string::FromUtf8Error) -> LogError {
-        LogError::UTF8Error(err)
+impl Transformed {
+    /// JSON of every parsed line, each followed by `delimiter`. With `keep_raw` the lines
+    /// that failed are written as they were, in their place.
+    fn to_vec(&self, delimiter: &str, keep_raw: bool) -> std::result::Result<Vec<u8>, LogError> {
+        let mut out = Vec::new();
+        let mut errors = self.errors.iter().filter(|_| keep_raw);
+        let mut next = errors.next();
+        for (i, line) in self.lines.iter().enumerate() {
+            while let Some(e) = next.filter(|e| e.at == i) {
+                out.extend_from_slice(e.raw.as_bytes());
+                out.extend_from_slice(delimiter.as_bytes());
+                next = errors.next();
+            }
+            serde_json::to_writer(&mut out, line)?;
+            out.extend_from_slice(delimiter.as_bytes());
+        }
+        for e in next.into_iter().chain(errors) {
+            out.extend_from_slice(e.raw.as_bytes());
+            out.extend_from_slice(delimiter.as_bytes());
+        }
+        Ok(out)
     }
-}
 
-impl From<chrono::ParseError> for LogError {
-    fn from(err: chrono::ParseError) -> LogError {
-        LogError::DateTimeParseError(err)
+    /// Applies `on_error` to the lines that failed: `ProcessingFailed` fails the whole
+    /// record with the first error, `Ok` keeps each raw line where it was and `Dropped`
+    /// leaves it out. `Ok(None)` when nothing is left to write.
+    fn into_output(mut self, delimiter: &str, on_error: RecordResult) -> std::result::Result<Option<Vec<u8>>, LogError> {
+        if on_error == RecordResult::ProcessingFailed && !self.errors.is_empty() {
+            return Err(self.errors.remove(0).error);
+        }
+        let keep_raw = on_error == RecordResult::Ok;
+        if self.lines.is_empty() && (!keep_raw || self.errors.is_empty()) {
+            return Ok(None);
+        }
+        self.to_vec(delimiter, keep_raw).map(Some)
     }
 }
 
-impl From<std::num::ParseIntError> for LogError {
-    fn from(err: std::num::ParseIntError) -> LogError {
-        LogError::IntError(err)
+/// Parses every line of a record. Lines matching `DROP_PATTERN` appear in neither
+/// `lines` nor `errors`.
+fn transform_data(config: &Config, ctx: &RecordContext, data: Vec<u8>) -> std::result::Result<Transformed, LogError> {
+    if cloudwatch::is_gzip(&data) {
+        return transform_cloudwatch_logs(config, &data);
     }
-}
 
-impl From<serde_json::Error> for LogError {
-    fn from(err: serde_json::Error) -> LogError {
-        LogError::JsonError(err)
-    }
+    let s = String::from_utf8(data)?;
+
+    let mut transformed = Transformed::default();
+    transform_text(config, ctx, &s, &mut transformed, |_| ());
+    Ok(transformed)
 }
 
-impl fmt::Display for LogError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            LogError::RegexParseError => fmt::Display::fmt(self, f),
-            LogError::UTF8Error(ref err) => fmt::Display::fmt(err, f),
-            LogError::DateTimeParseError(ref err) => fmt::Display::fmt(err, f),
-            LogError::IntError(ref err) => fmt::Display::fmt(err, f),
-            LogError::JsonError(ref err) => fmt::Display::fmt(err, f),
+/// Parses each non-empty line of `text` into `out`, letting `f` decorate the parsed value.
+/// Lines the parser skips, such as header directives, appear in neither list.
+fn transform_text<F>(config: &Config, ctx: &RecordContext, text: &str, out: &mut Transformed, f: F)
+    where F: Fn(&mut Value)
+{
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        if config.drop_pattern.as_ref().map_or(false, |re| re.is_match(line)) {
+            continue;
+        }
+        match config.parser.parse_with_context(line.as_bytes(), ctx) {
+            Ok(Value::Null) => (),
+            Ok(mut r) => {
+                f(&mut r);
+                out.lines.push(r);
+            }
+            Err(error) => out.errors.push(LineError { at: out.lines.len(), raw: line.to_owned(), error }),
         }
     }
 }
 
-impl std::error::Error for LogError {
-    fn description(&self) -> &str {
-        match *self {
-            LogError::RegexParseError => "FAIL. unmatched pattern.",
-            LogError::UTF8Error(ref err) => err.description(),
-            LogError::DateTimeParseError(ref err) => err.description(),
-            LogError::IntError(ref err) => err.description(),
-            LogError::JsonError(ref err) => err.description(),
-        }
+/// Parses every `logEvents[].message` of a gzipped CloudWatch Logs subscription payload,
+/// tagging each with its `logGroup` and `logStream`. Control messages are dropped.
+/// Each event's own timestamp stands in for the arrival time.
+fn transform_cloudwatch_logs(config: &Config, data: &[u8]) -> std::result::Result<Transformed, LogError> {
+    let logs = cloudwatch::decode(data)?;
+
+    let mut transformed = Transformed::default();
+    if logs.is_control_message() {
+        return Ok(transformed);
     }
+
+    for event in &logs.log_events {
+        let ctx = RecordContext { arrival_time: parser::from_epoch_millis(event.timestamp).ok(), ..RecordContext::default() };
+        transform_text(config, &ctx, &event.message, &mut transformed, |r| {
+            if let Some(m) = r.as_object_mut() {
+                m.insert("logGroup".to_owned(), Value::from(logs.log_group.clone()));
+                m.insert("logStream".to_owned(), Value::from(logs.log_stream.clone()));
+            }
+        });
+    }
+
+    Ok(transformed)
 }
 
-fn apache_log2json(s: &str) -> Result<serde_json::Value, LogError> {
-    let xs = RE.captures(s).ok_or(LogError::RegexParseError)?;
+#[test]
+fn transform_data_test() {
+    let config = Config::default();
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "GET /explore" 200 9947 "-" "Mozilla/5.0 (Windows NT 6.2; WOW64; rv:8.5) Gecko/20100101 Firefox/8.5.1" "#;
+    let a = transform_data(&config, &RecordContext::default(), data.as_bytes().to_vec()).unwrap();
 
-    let time =
-        DateTime::parse_from_str(&xs[4], "%d/%b/%Y:%H:%M:%S %:z")
-            .or(DateTime::parse_from_str(&xs[4], "%d/%b/%Y:%H:%M:%S %z"))?;
-    xs[6].parse::<u32>()?;
+    assert_eq!(a.lines.len(), 1);
+    assert!(a.errors.is_empty());
+    let line = &a.lines[0];
+    assert_eq!(line["host"], "7.248.7.119");
+    assert_eq!(line["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+    assert_eq!(line["method"], "GET");
+    assert_eq!(line["path"], "/explore");
+    assert_eq!(line["response"], 200);
+    assert_eq!(line["bytes"], 9947);
+    assert_eq!(line["referer"], "-");
+    assert_eq!(line["user_agent"], "Mozilla/5.0 (Windows NT 6.2; WOW64; rv:8.5) Gecko/20100101 Firefox/8.5.1");
 
-    let log =  AccessLog {
-        host: xs[1].to_owned(),
-        ident: xs[2].to_owned(),
-        authuser: xs[3].to_owned(),
-        timestamp: time.to_rfc3339(),
-        timestamp_utc: time.with_timezone(&Utc).to_rfc3339(),
-        request: xs[5].to_owned(),
-        response: xs[6].parse::<u32>()?,
-        bytes: xs[7].parse::<u32>()?,
-    };
-    serde_json::to_value(log).map_err(|e| LogError::JsonError(e))
+    let out = String::from_utf8(a.to_vec(&config.record_delimiter, false).unwrap()).unwrap();
+    assert_eq!(out.matches('\n').count(), 1);
+    assert!(out.ends_with("}\n"));
+    assert_eq!(&serde_json::from_str::<Value>(out.trim_end()).unwrap(), line);
 }
 
-fn transform_data(data: Vec<u8>) -> std::result::Result<Vec<u8>, LogError> {
-    let s = String::from_utf8(data)?;
+#[test]
+fn transform_data_multi_line_test() {
+    let config = Config::default();
+    let data = "7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] \"GET /explore\" 200 9947\r\n\
+                garbage\n\
+                \n\
+                7.248.7.119 - - [14/Dec/2017:22:16:46 +09:00] \"GET /about\" 200 512\n";
+    let a = transform_data(&config, &RecordContext::default(), data.as_bytes().to_vec()).unwrap();
+
+    assert_eq!(a.lines.len(), 2);
+    assert_eq!(a.errors.len(), 1);
+    assert_eq!(a.lines[1]["path"], "/about");
 
-    let r = apache_log2json(s.as_str())?;
+    let out = String::from_utf8(a.to_vec(&config.record_delimiter, false).unwrap()).unwrap();
+    assert_eq!(out.lines().count(), 2);
+    assert!(out.ends_with("}\n"));
 
-    serde_json::to_vec(&r).map_err(|e| LogError::JsonError(e))
+    let out = String::from_utf8(a.to_vec("", false).unwrap()).unwrap();
+    assert!(out.contains("}{"));
 }
 
 #[test]
-fn transform_data_test() {
-    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "GET /explore" 200 9947 "-" "Mozilla/5.0 (Windows NT 6.2; WOW64; rv:8.5) Gecko/20100101 Firefox/8.5.1" "#;
-    let a = apache_log2json(data).unwrap();
+fn on_parse_error_test() {
+    let config = Config::default();
+    let data = "garbage\n\
+                7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] \"GET /explore\" 200 9947\n\
+                more garbage\n";
+    let transform = || transform_data(&config, &RecordContext::default(), data.as_bytes().to_vec()).unwrap();
+
+    assert!(transform().into_output("\n", RecordResult::ProcessingFailed).is_err());
+
+    let out = String::from_utf8(transform().into_output("\n", RecordResult::Ok).unwrap().unwrap()).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "garbage");
+    assert!(lines[1].starts_with('{'));
+    assert_eq!(lines[2], "more garbage");
+
+    let out = String::from_utf8(transform().into_output("\n", RecordResult::Dropped).unwrap().unwrap()).unwrap();
+    assert_eq!(out.lines().count(), 1);
+
+    let garbage = || transform_data(&config, &RecordContext::default(), b"garbage".to_vec()).unwrap();
+    assert_eq!(garbage().into_output("\n", RecordResult::Ok).unwrap().unwrap(), b"garbage\n");
+    assert!(garbage().into_output("\n", RecordResult::Dropped).unwrap().is_none());
+}
+
+#[test]
+fn transform_data_drop_test() {
+    let config = Config {
+        drop_pattern: Some(regex::Regex::new(r#"GET /health"#).unwrap()),
+        ..Config::default()
+    };
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "GET /health" 200 2"#;
+    let a = transform_data(&config, &RecordContext::default(), data.as_bytes().to_vec()).unwrap();
+    assert!(a.lines.is_empty() && a.errors.is_empty());
+
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "GET /explore" 200 9947"#;
+    assert_eq!(transform_data(&config, &RecordContext::default(), data.as_bytes().to_vec()).unwrap().lines.len(), 1);
+
+    assert_eq!(transform_data(&config, &RecordContext::default(), b"garbage".to_vec()).unwrap().errors.len(), 1);
+}
+
+#[test]
+fn transform_cloudwatch_logs_test() {
+    let config = Config::default();
+    let data = cloudwatch::encode(r#"{"messageType":"DATA_MESSAGE","owner":"123456789012","logGroup":"/var/log/httpd","logStream":"i-0123456789abcdef0","subscriptionFilters":["apache"],"logEvents":[
+        {"id":"1","timestamp":1513257405000,"message":"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] \"GET /explore\" 200 9947"},
+        {"id":"2","timestamp":1513257406000,"message":"7.248.7.119 - - [14/Dec/2017:22:16:46 +09:00] \"GET /about\" 200 512"}]}"#);
+    let lines = transform_data(&config, &RecordContext::default(), data).unwrap().lines;
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["logGroup"], "/var/log/httpd");
+    assert_eq!(lines[1]["logStream"], "i-0123456789abcdef0");
+    assert_eq!(lines[1]["path"], "/about");
 
-    println!("{}", a);
+    let data = cloudwatch::encode(r#"{"messageType":"CONTROL_MESSAGE","owner":"CloudwatchLogs","logGroup":"","logStream":"","subscriptionFilters":[],"logEvents":[]}"#);
+    assert!(transform_data(&config, &RecordContext::default(), data).unwrap().lines.is_empty());
 }
 
-fn transform_record(record: KinesisFirehoseEventRecord) -> KinesisFirehoseResponseRecord {
+/// `approximateArrivalTimestamp` of the record. It is read through its serialized form,
+/// milliseconds since the epoch, so the event type's own chrono version doesn't matter.
+fn arrival_time(record: &KinesisFirehoseEventRecord) -> Option<DateTime<Utc>> {
+    serde_json::to_value(&record.approximate_arrival_timestamp).ok()
+        .and_then(|v| v.as_f64())
+        .and_then(|ms| parser::from_epoch_millis(ms as i64).ok())
+}
+
+/// A record passed through with `ON_PARSE_ERROR=Ok`, ending in the delimiter like parsed
+/// output so it stays apart from the next record once Firehose concatenates them.
+fn passthrough(data: &[u8], delimiter: &str) -> Vec<u8> {
+    let mut out = data.to_vec();
+    if !out.ends_with(delimiter.as_bytes()) {
+        out.extend_from_slice(delimiter.as_bytes());
+    }
+    out
+}
+
+#[test]
+fn passthrough_test() {
+    assert_eq!(passthrough(b"garbage", "\n"), b"garbage\n");
+    assert_eq!(passthrough(b"garbage\n", "\n"), b"garbage\n");
+    assert_eq!(passthrough(b"garbage", ""), b"garbage");
+}
+
+fn transform_record(config: &Config, record: KinesisFirehoseEventRecord) -> FirehoseResponseRecord {
     let id = record.record_id.clone();
-    transform_data(record.data.as_slice().to_vec())
-        .map(|x|
-            KinesisFirehoseResponseRecord {
-                record_id: id.clone(),
-                data: Base64Data::new(x),
-                result: None,
-            }
-        )
-        .unwrap_or(
-            KinesisFirehoseResponseRecord {
-                record_id: id,
-                data: record.data,
-                result: None,
+    let ctx = RecordContext { arrival_time: arrival_time(&record), ..RecordContext::default() };
+    let log_error = |e: LogError| {
+        eprintln!("record {:?}: {}", id, e);
+        e
+    };
+    let transformed = transform_data(config, &ctx, record.data.as_slice().to_vec())
+        .map_err(&log_error)
+        .and_then(|t| {
+            for e in &t.errors {
+                eprintln!("record {:?}: {}", id, e.error);
             }
-        )
+            let metadata = partition::metadata(&config.partition_keys, &t.lines)
+                .map_err(&log_error)?;
+            Ok(t.into_output(&config.record_delimiter, config.on_parse_error)?.map(|x| (x, metadata)))
+        });
+    let (data, result, metadata) = match transformed {
+        Ok(Some((x, metadata))) => (Base64Data::new(x), RecordResult::Ok, metadata),
+        Ok(None) => (record.data, RecordResult::Dropped, None),
+        Err(_) if config.on_parse_error == RecordResult::Ok => {
+            (Base64Data::new(passthrough(record.data.as_slice(), &config.record_delimiter)), RecordResult::Ok, None)
+        }
+        Err(_) => (record.data, config.on_parse_error, None),
+    };
+    FirehoseResponseRecord {
+        record: KinesisFirehoseResponseRecord {
+            record_id: id,
+            data,
+            result: Some(result.as_str().to_owned()),
+        },
+        metadata,
+    }
 }
 
-fn my_handler(event: KinesisFirehoseEvent) -> KinesisFirehoseResponse {
-    KinesisFirehoseResponse {
+fn my_handler(config: &Config, event: KinesisFirehoseEvent) -> FirehoseResponse {
+    FirehoseResponse {
         records: event.records.into_par_iter()
-            .map(|x| transform_record(x))
-            .collect::<Vec<KinesisFirehoseResponseRecord>>(),
+            .map(|x| transform_record(config, x))
+            .collect::<Vec<FirehoseResponseRecord>>(),
     }
-}
\ No newline at end of file
+}
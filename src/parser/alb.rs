@@ -0,0 +1,101 @@
+use chrono::DateTime;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{insert_timestamp, LogParser};
+use super::fields::{address, float, int, list, split_fields, string};
+use super::request::parse_request_line;
+
+/// Fields every ALB log entry has, up to `target_group_arn`.
+const REQUIRED_FIELDS: usize = 17;
+
+/// AWS Application Load Balancer access log.
+///
+/// Latencies are float seconds (`-1` when the request could not be dispatched) and the
+/// `client:port` and `target:port` pairs are split into `*_ip` and `*_port`.
+#[derive(Debug, Default)]
+pub struct AlbParser;
+
+impl LogParser for AlbParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let xs = split_fields(&s);
+        if xs.len() < REQUIRED_FIELDS {
+            return Err(LogError::RegexParseError);
+        }
+
+        let mut m = Map::new();
+        let (client_ip, client_port) = address(xs[3])?;
+        let (target_ip, target_port) = address(xs[4])?;
+        m.insert("type".to_owned(), string(xs[0]));
+        insert_timestamp(&mut m, &DateTime::parse_from_rfc3339(xs[1])?);
+        m.insert("elb".to_owned(), string(xs[2]));
+        m.insert("client_ip".to_owned(), client_ip);
+        m.insert("client_port".to_owned(), client_port);
+        m.insert("target_ip".to_owned(), target_ip);
+        m.insert("target_port".to_owned(), target_port);
+        m.insert("request_processing_time".to_owned(), float(xs[5])?);
+        m.insert("target_processing_time".to_owned(), float(xs[6])?);
+        m.insert("response_processing_time".to_owned(), float(xs[7])?);
+        m.insert("elb_status_code".to_owned(), int(xs[8])?);
+        m.insert("target_status_code".to_owned(), int(xs[9])?);
+        m.insert("received_bytes".to_owned(), int(xs[10])?);
+        m.insert("sent_bytes".to_owned(), int(xs[11])?);
+        m.insert("request".to_owned(), string(xs[12]));
+        if let Value::Object(r) = serde_json::to_value(parse_request_line(xs[12]))? {
+            m.extend(r);
+        }
+        m.insert("user_agent".to_owned(), string(xs[13]));
+        m.insert("ssl_cipher".to_owned(), string(xs[14]));
+        m.insert("ssl_protocol".to_owned(), string(xs[15]));
+        m.insert("target_group_arn".to_owned(), string(xs[16]));
+
+        // Fields AWS appended over time; older entries stop earlier.
+        let optional = xs.iter().skip(REQUIRED_FIELDS);
+        let names = [
+            "trace_id", "domain_name", "chosen_cert_arn", "matched_rule_priority",
+            "request_creation_time", "actions_executed", "redirect_url", "error_reason",
+            "target_port_list", "target_status_code_list", "classification",
+            "classification_reason", "conn_trace_id",
+        ];
+        for (&name, &x) in names.iter().zip(optional) {
+            let v = match name {
+                "matched_rule_priority" => int(x)?,
+                "actions_executed" => list(x, ','),
+                "target_port_list" | "target_status_code_list" => list(x, ' '),
+                _ => string(x),
+            };
+            m.insert(name.to_owned(), v);
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+#[test]
+fn alb_test() {
+    let data = r#"https 2018-07-02T22:23:00.186641Z app/my-loadbalancer/50dc6c495c0c9188 192.168.131.39:2817 10.0.0.1:80 0.086 0.048 0.037 200 200 0 57 "GET https://www.example.com:443/?a=1 HTTP/1.1" "curl/7.46.0" ECDHE-RSA-AES128-GCM-SHA256 TLSv1.2 arn:aws:elasticloadbalancing:us-east-2:123456789012:targetgroup/my-targets/73e2d6bc24d8a067 "Root=1-58337281-1d84f3d73c47ec4e58577259" "www.example.com" "arn:aws:acm:us-east-2:123456789012:certificate/12345678-1234-1234-1234-123456789012" 1 2018-07-02T22:22:48.364000Z "authenticate,forward" "-" "-" "10.0.0.1:80 10.0.0.2:80" "200 200" "-" "-""#;
+    let a = AlbParser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["@timestamp_utc"], "2018-07-02T22:23:00.186641+00:00");
+    assert_eq!(a["client_ip"], "192.168.131.39");
+    assert_eq!(a["client_port"], 2817);
+    assert_eq!(a["target_processing_time"], 0.048);
+    assert_eq!(a["elb_status_code"], 200);
+    assert_eq!(a["sent_bytes"], 57);
+    assert_eq!(a["method"], "GET");
+    assert_eq!(a["query_params"]["a"], "1");
+    assert_eq!(a["user_agent"], "curl/7.46.0");
+    assert_eq!(a["trace_id"], "Root=1-58337281-1d84f3d73c47ec4e58577259");
+    assert_eq!(a["matched_rule_priority"], 1);
+    assert_eq!(a["actions_executed"][1], "forward");
+    assert_eq!(a["target_status_code_list"][1], "200");
+    assert!(a.get("conn_trace_id").is_none());
+
+    let data = r#"http 2018-07-02T22:23:00.186641Z app/my-loadbalancer/50dc6c495c0c9188 192.168.131.39:2817 - -1 -1 -1 503 - 34 366 "GET http://www.example.com:80/ HTTP/1.1" "curl/7.46.0" - - -"#;
+    let a = AlbParser.parse(data.as_bytes()).unwrap();
+    assert!(a["target_ip"].is_null());
+    assert_eq!(a["request_processing_time"], -1.0);
+    assert!(a["target_status_code"].is_null());
+
+    assert!(AlbParser.parse(b"http 2018-07-02T22:23:00.186641Z").is_err());
+}
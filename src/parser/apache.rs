@@ -0,0 +1,477 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use chrono::prelude::*;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{from_epoch, insert_timestamp, LogParser};
+use super::request::parse_request_line;
+
+/// `LogFormat "%h %l %u %t \"%r\" %>s %b" common`
+pub const COMMON: &str = r#"%h %l %u %t "%r" %>s %b"#;
+
+/// `LogFormat "%h %l %u %t \"%r\" %>s %b \"%{Referer}i\" \"%{User-agent}i\"" combined`
+pub const COMBINED: &str = r#"%h %l %u %t "%r" %>s %b "%{Referer}i" "%{User-agent}i""#;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HostType {
+    Ipv4,
+    Ipv6,
+    Hostname,
+}
+
+/// Classifies a client address as written by Apache: an IP literal, or a DNS name
+/// when `HostnameLookups On` is set.
+pub fn host_type(host: &str) -> Result<HostType, LogError> {
+    if host.parse::<Ipv4Addr>().is_ok() {
+        return Ok(HostType::Ipv4);
+    }
+    if host.parse::<Ipv6Addr>().is_ok() {
+        return Ok(HostType::Ipv6);
+    }
+    let valid_label = |l: &str| {
+        !l.is_empty() && l.len() <= 63
+            && !l.starts_with('-') && !l.ends_with('-')
+            && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    let name = host.trim_end_matches('.');
+    if !name.is_empty() && name.len() <= 253
+        && name.split('.').all(valid_label)
+        && !name.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        Ok(HostType::Hostname)
+    } else {
+        Err(LogError::HostParseError(host.to_owned()))
+    }
+}
+
+/// How the text captured for a directive is turned into JSON.
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Str,
+    Int,
+    Host,
+    Request,
+    Status,
+    QueryString,
+    /// `%t`, the Common Log Format timestamp in brackets.
+    Time,
+    /// `%{sec}t`, `%{msec}t` and `%{usec}t`: the number of units per second.
+    Epoch(i64),
+    /// `%{format}t` with a strftime format.
+    TimeFormat(String),
+}
+
+#[derive(Debug)]
+struct Directive {
+    name: String,
+    kind: Kind,
+}
+
+impl Directive {
+    /// Maps `%<letter>` with its optional `{argument}` to an output field. The Common and
+    /// Combined directives give `host`, `authuser`, `response`, `bytes`, `referer` and
+    /// `user_agent`.
+    fn new(letter: char, arg: Option<&str>) -> Result<Directive, LogError> {
+        let header = |prefix: &str, name: &str| {
+            format!("{}{}", prefix, name.to_lowercase().replace('-', "_"))
+        };
+        let (name, kind) = match (letter, arg) {
+            ('h', _) => ("host".to_owned(), Kind::Host),
+            ('a', Some("c")) => ("peer_ip".to_owned(), Kind::Str),
+            ('a', _) => ("client_ip".to_owned(), Kind::Str),
+            ('A', _) => ("local_ip".to_owned(), Kind::Str),
+            ('l', _) => ("ident".to_owned(), Kind::Str),
+            ('u', _) => ("authuser".to_owned(), Kind::Str),
+            ('t', None) => ("@timestamp".to_owned(), Kind::Time),
+            ('t', Some(f)) => {
+                let f = f.trim_start_matches("begin:").trim_start_matches("end:");
+                let kind = match f {
+                    "sec" => Kind::Epoch(1),
+                    "msec" => Kind::Epoch(1_000),
+                    "usec" => Kind::Epoch(1_000_000),
+                    "msec_frac" | "usec_frac" => return Ok(Directive { name: format!("time_{}", f), kind: Kind::Int }),
+                    _ => Kind::TimeFormat(f.to_owned()),
+                };
+                ("@timestamp".to_owned(), kind)
+            }
+            ('r', _) => ("request".to_owned(), Kind::Request),
+            ('s', _) => ("response".to_owned(), Kind::Status),
+            ('b', _) | ('B', _) => ("bytes".to_owned(), Kind::Int),
+            ('I', _) => ("bytes_received".to_owned(), Kind::Int),
+            ('O', _) => ("bytes_sent".to_owned(), Kind::Int),
+            ('S', _) => ("bytes_transferred".to_owned(), Kind::Int),
+            ('D', _) => ("duration_us".to_owned(), Kind::Int),
+            ('T', Some("ms")) => ("duration_ms".to_owned(), Kind::Int),
+            ('T', Some("us")) => ("duration_us".to_owned(), Kind::Int),
+            ('T', _) => ("duration_s".to_owned(), Kind::Int),
+            ('k', _) => ("keepalive_requests".to_owned(), Kind::Int),
+            ('p', Some("remote")) => ("remote_port".to_owned(), Kind::Int),
+            ('p', Some("local")) => ("local_port".to_owned(), Kind::Int),
+            ('p', _) => ("port".to_owned(), Kind::Int),
+            ('P', Some("tid")) | ('P', Some("hextid")) => ("tid".to_owned(), Kind::Str),
+            ('P', _) => ("pid".to_owned(), Kind::Int),
+            ('v', _) => ("vhost".to_owned(), Kind::Str),
+            ('V', _) => ("server_name".to_owned(), Kind::Str),
+            ('U', _) => ("path".to_owned(), Kind::Str),
+            ('q', _) => ("query_string".to_owned(), Kind::QueryString),
+            ('m', _) => ("method".to_owned(), Kind::Str),
+            ('H', _) => ("protocol".to_owned(), Kind::Str),
+            ('X', _) => ("connection_status".to_owned(), Kind::Str),
+            ('L', _) => ("log_id".to_owned(), Kind::Str),
+            ('R', _) => ("handler".to_owned(), Kind::Str),
+            ('f', _) => ("filename".to_owned(), Kind::Str),
+            ('i', Some(h)) => (header("", h), Kind::Str),
+            ('o', Some(h)) => (header("response_", h), Kind::Str),
+            ('e', Some(h)) => (header("env_", h), Kind::Str),
+            ('n', Some(h)) => (header("note_", h), Kind::Str),
+            ('C', Some(h)) => (header("cookie_", h), Kind::Str),
+            _ => return Err(LogError::ConfigError(format!("unsupported LogFormat directive: %{}", letter))),
+        };
+        Ok(Directive { name, kind })
+    }
+
+    /// Regex for the directive's text. Strings inside double quotes may contain `\"`.
+    fn pattern(&self, quoted: bool) -> String {
+        let pattern = match self.kind {
+            Kind::Int | Kind::Epoch(_) => r"(-|\d+)",
+            Kind::Status => r"(-|\d{3})",
+            Kind::Time => r"\[([^\]]*)\]",
+            Kind::TimeFormat(ref f) => return format!("({})", strftime_pattern(f)),
+            _ if quoted => r#"((?:[^"\\]|\\.)*)"#,
+            Kind::Request => r"(.+?)",
+            _ => r"(\S*)",
+        };
+        pattern.to_owned()
+    }
+
+    fn insert(&self, m: &mut Map<String, Value>, s: &str) -> Result<(), LogError> {
+        let v = match self.kind {
+            Kind::Str => Value::from(s),
+            Kind::Int | Kind::Status if s == "-" => Value::Null,
+            Kind::Int => Value::from(s.parse::<u64>()?),
+            Kind::Status => Value::from(s.parse::<u32>()?),
+            Kind::Host => {
+                m.insert("host_type".to_owned(), serde_json::to_value(host_type(s)?)?);
+                Value::from(s)
+            }
+            Kind::Request => {
+                if let Value::Object(r) = serde_json::to_value(parse_request_line(s))? {
+                    m.extend(r);
+                }
+                Value::from(s)
+            }
+            Kind::QueryString => Value::from(s.trim_start_matches('?')),
+            Kind::Time => {
+                insert_timestamp(m, &parse_clf_time(s)?);
+                return Ok(());
+            }
+            Kind::Epoch(_) if s == "-" => return Ok(()),
+            Kind::Epoch(per_sec) => {
+                let t = s.parse::<i64>()?;
+                let nanos = (t % per_sec) * (1_000_000_000 / per_sec);
+                insert_timestamp(m, &from_epoch(t / per_sec, nanos as u32)?);
+                return Ok(());
+            }
+            Kind::TimeFormat(ref f) => {
+                match DateTime::parse_from_str(s, f) {
+                    Ok(t) => insert_timestamp(m, &t),
+                    Err(_) => insert_timestamp(m, &Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(s, f)?)),
+                }
+                return Ok(());
+            }
+        };
+        m.insert(self.name.clone(), v);
+        Ok(())
+    }
+}
+
+/// An Apache `LogFormat` string compiled into a regex, once at cold start.
+#[derive(Debug)]
+pub struct LogFormat {
+    regex: Regex,
+    directives: Vec<Directive>,
+}
+
+impl LogFormat {
+    /// Compiles a `LogFormat` string as written in httpd.conf, so `\"` is accepted for `"`.
+    pub fn compile(format: &str) -> Result<LogFormat, LogError> {
+        let invalid = || LogError::ConfigError(format!("invalid LogFormat: {}", format));
+        let format = format.replace("\\\"", "\"").replace("\\t", "\t").replace("\\n", "\n");
+
+        let mut pattern = "^".to_owned();
+        let mut directives = Vec::new();
+        let mut literal = String::new();
+        let mut has_time = false;
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                literal.push(c);
+                continue;
+            }
+            // Status conditions such as `%400,501{User-agent}i` and the `<`/`>`
+            // original/final request modifiers don't change the text logged.
+            while let Some(&m) = chars.peek() {
+                if m == '<' || m == '>' || m == '!' || m == ',' || m.is_ascii_digit() {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let arg = if chars.peek() == Some(&'{') {
+                chars.next();
+                let arg = chars.by_ref().take_while(|&c| c != '}').collect::<String>();
+                Some(arg)
+            } else {
+                None
+            };
+            let letter = chars.next().ok_or_else(&invalid)?;
+            if letter == '%' {
+                literal.push('%');
+                continue;
+            }
+
+            let directive = Directive::new(letter, arg.as_deref())?;
+            if directive.name == "@timestamp" {
+                if has_time {
+                    return Err(LogError::ConfigError(format!("more than one time directive in LogFormat: {}", format)));
+                }
+                has_time = true;
+            }
+            pattern.push_str(&regex::escape(&literal));
+            pattern.push_str(&directive.pattern(literal.ends_with('"')));
+            directives.push(directive);
+            literal.clear();
+        }
+        pattern.push_str(&regex::escape(&literal));
+        pattern.push_str(r"\s*$");
+
+        let regex = Regex::new(&pattern).map_err(|_| invalid())?;
+        Ok(LogFormat { regex, directives })
+    }
+}
+
+/// Regex for the text of a strftime format, built from its own specifiers so that
+/// spaces and separators inside it line up with the rest of the `LogFormat`.
+fn strftime_pattern(format: &str) -> String {
+    let mut pattern = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            pattern.push_str(&regex::escape(&c.to_string()));
+            continue;
+        }
+        let mut spec = String::new();
+        for c in chars.by_ref() {
+            spec.push(c);
+            if c.is_ascii_alphabetic() || c == '%' {
+                break;
+            }
+        }
+        let p = match spec.as_str() {
+            "Y" => r"\d{4}",
+            "C" | "y" => r"\d{2}",
+            "m" | "d" | "H" | "I" | "M" | "S" => r"\d{1,2}",
+            "e" | "k" | "l" => r" ?\d{1,2}",
+            "j" => r"\d{1,3}",
+            "b" | "h" | "a" => r"[A-Za-z]{3}",
+            "B" | "A" => r"[A-Za-z]+",
+            "p" | "P" => r"[AaPp][Mm]",
+            "z" => r"[+-]\d{2}:?\d{2}",
+            ":z" => r"[+-]\d{2}:\d{2}",
+            "Z" => r"[A-Za-z]+",
+            "s" | "f" => r"\d+",
+            ".f" | ".3f" | ".6f" | ".9f" => r"\.\d+",
+            "F" => r"\d{4}-\d{1,2}-\d{1,2}",
+            "T" => r"\d{1,2}:\d{2}:\d{2}",
+            "R" => r"\d{1,2}:\d{2}",
+            "D" => r"\d{2}/\d{2}/\d{2}",
+            "n" | "t" => r"\s",
+            "%" => "%",
+            _ => r".+?",
+        };
+        pattern.push_str(p);
+    }
+    pattern
+}
+
+/// Apache access log. `APACHE_LOG_FORMAT` takes a `LogFormat` string; by default the
+/// Combined Log Format is tried first, then Common Log Format.
+///
+/// `EMPTY_BYTES` controls the `-` Apache writes for an empty response body:
+/// `null` (the default) or `zero`.
+#[derive(Debug)]
+pub struct ApacheParser {
+    pub formats: Vec<LogFormat>,
+    pub empty_bytes_as_zero: bool,
+}
+
+impl Default for ApacheParser {
+    fn default() -> ApacheParser {
+        ApacheParser {
+            formats: vec![LogFormat::compile(COMBINED).unwrap(), LogFormat::compile(COMMON).unwrap()],
+            empty_bytes_as_zero: false,
+        }
+    }
+}
+
+impl ApacheParser {
+    pub fn from_env() -> Result<ApacheParser, LogError> {
+        let mut parser = ApacheParser::default();
+        if let Ok(s) = std::env::var("APACHE_LOG_FORMAT") {
+            parser.formats = vec![LogFormat::compile(&s)?];
+        }
+        parser.empty_bytes_as_zero = match std::env::var("EMPTY_BYTES") {
+            Ok(ref s) if s == "zero" => true,
+            Ok(ref s) if s == "null" => false,
+            Ok(s) => return Err(LogError::ConfigError(format!("unknown EMPTY_BYTES: {}", s))),
+            Err(_) => false,
+        };
+        Ok(parser)
+    }
+}
+
+impl LogParser for ApacheParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let mut log = apache_log2json(&self.formats, &s)?;
+        if self.empty_bytes_as_zero && log.get("bytes") == Some(&Value::Null) {
+            log["bytes"] = Value::from(0);
+        }
+        Ok(log)
+    }
+}
+
+/// Parses the `[14/Dec/2017:22:16:45 +09:00]` style timestamp of Common Log Format,
+/// without the brackets. The offset may be written with or without a colon.
+pub fn parse_clf_time(s: &str) -> Result<DateTime<FixedOffset>, LogError> {
+    DateTime::parse_from_str(s, "%d/%b/%Y:%H:%M:%S %:z")
+        .or(DateTime::parse_from_str(s, "%d/%b/%Y:%H:%M:%S %z"))
+        .map_err(LogError::from)
+}
+
+/// Parses `s` with the first of `formats` that matches it.
+pub fn apache_log2json(formats: &[LogFormat], s: &str) -> Result<Value, LogError> {
+    let (format, xs) = formats.iter()
+        .find_map(|f| f.regex.captures(s).map(|xs| (f, xs)))
+        .ok_or(LogError::RegexParseError)?;
+
+    let mut m = Map::new();
+    for (directive, x) in format.directives.iter().zip(xs.iter().skip(1)) {
+        if let Some(x) = x {
+            directive.insert(&mut m, x.as_str())?;
+        }
+    }
+    Ok(Value::Object(m))
+}
+
+#[test]
+fn apache_log2json_test() {
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "GET /explore" 200 9947 "-" "Mozilla/5.0 (Windows NT 6.2; WOW64; rv:8.5) Gecko/20100101 Firefox/8.5.1" "#;
+    let a = ApacheParser::default().parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["host"], "7.248.7.119");
+    assert_eq!(a["@timestamp"], "2017-12-14T22:16:45+09:00");
+    assert_eq!(a["response"], 200);
+    assert_eq!(a["bytes"], 9947);
+    assert_eq!(a["referer"], "-");
+    assert_eq!(a["method"], "GET");
+    assert_eq!(a["path"], "/explore");
+    assert_eq!(a["user_agent"], "Mozilla/5.0 (Windows NT 6.2; WOW64; rv:8.5) Gecko/20100101 Firefox/8.5.1");
+}
+
+#[test]
+fn common_log_format_test() {
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "GET /explore" 200 9947"#;
+    let a = ApacheParser::default().parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["bytes"], 9947);
+    assert!(a["referer"].is_null());
+    assert!(a["user_agent"].is_null());
+}
+
+#[test]
+fn host_type_test() {
+    assert_eq!(host_type("7.248.7.119").unwrap(), HostType::Ipv4);
+    assert_eq!(host_type("2001:db8::1").unwrap(), HostType::Ipv6);
+    assert_eq!(host_type("proxy.example.com").unwrap(), HostType::Hostname);
+    assert!(host_type("999.1.1.1").is_err());
+    assert!(host_type("-bad-.example.com").is_err());
+    assert!(host_type("a..b").is_err());
+
+    let data = r#"2001:db8::1 - - [14/Dec/2017:22:16:45 +09:00] "GET /explore" 200 9947"#;
+    let a = ApacheParser::default().parse(data.as_bytes()).unwrap();
+    assert_eq!(a["host"], "2001:db8::1");
+    assert_eq!(a["host_type"], "ipv6");
+}
+
+#[test]
+fn malformed_request_line_test() {
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "-" 408 0"#;
+    let a = ApacheParser::default().parse(data.as_bytes()).unwrap();
+    assert_eq!(a["request"], "-");
+    assert!(a["method"].is_null());
+    assert!(a["path"].is_null());
+}
+
+#[test]
+fn bytes_test() {
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "GET /explore" 304 -"#;
+    let a = ApacheParser::default().parse(data.as_bytes()).unwrap();
+    assert!(a["bytes"].is_null());
+
+    let parser = ApacheParser { empty_bytes_as_zero: true, ..ApacheParser::default() };
+    let a = parser.parse(data.as_bytes()).unwrap();
+    assert_eq!(a["bytes"], 0);
+
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +09:00] "GET /big.iso" 200 5368709120"#;
+    let a = ApacheParser::default().parse(data.as_bytes()).unwrap();
+    assert_eq!(a["bytes"], 5368709120u64);
+}
+
+#[test]
+fn log_format_test() {
+    let format = LogFormat::compile(r#"%h %l %u %t \"%r\" %>s %b %D \"%{Referer}i\" \"%{X-Forwarded-For}i\" %v %{ms}T"#).unwrap();
+    let data = r#"proxy.example.com - bob [14/Dec/2017:22:16:45 +09:00] "GET /a?b=c HTTP/1.1" 200 - 1534 "http://example.com/\"x\"" "10.0.0.1, 10.0.0.2" www.example.com 1"#;
+    let a = apache_log2json(&[format], data).unwrap();
+
+    assert_eq!(a["host_type"], "hostname");
+    assert_eq!(a["authuser"], "bob");
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+    assert_eq!(a["query_params"]["b"], "c");
+    assert_eq!(a["response"], 200);
+    assert!(a["bytes"].is_null());
+    assert_eq!(a["duration_us"], 1534);
+    assert_eq!(a["duration_ms"], 1);
+    assert_eq!(a["referer"], r#"http://example.com/\"x\""#);
+    assert_eq!(a["x_forwarded_for"], "10.0.0.1, 10.0.0.2");
+    assert_eq!(a["vhost"], "www.example.com");
+
+    assert!(LogFormat::compile("%h %Z").is_err());
+    assert!(LogFormat::compile("%h %").is_err());
+}
+
+#[test]
+fn time_format_test() {
+    let format = LogFormat::compile("%{%Y-%m-%d %H:%M:%S}t %h %u").unwrap();
+    let a = apache_log2json(&[format], "2017-12-14 13:16:45 7.248.7.119 bob").unwrap();
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+    assert_eq!(a["host"], "7.248.7.119");
+    assert_eq!(a["authuser"], "bob");
+
+    let format = LogFormat::compile("[%{%d/%b/%Y:%H:%M:%S %z}t] %h").unwrap();
+    let a = apache_log2json(&[format], "[14/Dec/2017:22:16:45 +0900] 7.248.7.119").unwrap();
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+
+    let format = LogFormat::compile("%h %{msec}t").unwrap();
+    let a = apache_log2json(&[format], "7.248.7.119 1513257405123").unwrap();
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45.123+00:00");
+
+    // The pattern is anchored, so trailing text isn't silently ignored.
+    let format = LogFormat::compile("%h %u").unwrap();
+    assert!(apache_log2json(&[format], "7.248.7.119 bob extra").is_err());
+
+    assert!(LogFormat::compile("%t %{msec}t").is_err());
+    assert!(LogFormat::compile("%{%Y-%m-%d}t %{%H:%M:%S}t").is_err());
+}
+
@@ -0,0 +1,340 @@
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{from_epoch_millis, insert_timestamp, LogParser};
+use super::fields::int;
+
+/// Readable names of the CEF extension keys ArcSight defines in short form.
+fn cef_name(key: &str) -> Option<&'static str> {
+    match key {
+        "act" => Some("device_action"),
+        "app" => Some("application_protocol"),
+        "cat" => Some("device_event_category"),
+        "cnt" => Some("base_event_count"),
+        "dhost" => Some("destination_host_name"),
+        "dmac" => Some("destination_mac_address"),
+        "dntdom" => Some("destination_nt_domain"),
+        "dpid" => Some("destination_process_id"),
+        "dpriv" => Some("destination_user_privileges"),
+        "dproc" => Some("destination_process_name"),
+        "dpt" => Some("destination_port"),
+        "dst" => Some("destination_address"),
+        "duid" => Some("destination_user_id"),
+        "duser" => Some("destination_user_name"),
+        "dvc" => Some("device_address"),
+        "dvchost" => Some("device_host_name"),
+        "dvcmac" => Some("device_mac_address"),
+        "dvcpid" => Some("device_process_id"),
+        "end" => Some("end_time"),
+        "fname" => Some("file_name"),
+        "fsize" => Some("file_size"),
+        "in" => Some("bytes_in"),
+        "msg" => Some("message"),
+        "out" => Some("bytes_out"),
+        "outcome" => Some("event_outcome"),
+        "proto" => Some("transport_protocol"),
+        "request" => Some("request_url"),
+        "rt" => Some("device_receipt_time"),
+        "shost" => Some("source_host_name"),
+        "smac" => Some("source_mac_address"),
+        "sntdom" => Some("source_nt_domain"),
+        "spid" => Some("source_process_id"),
+        "spriv" => Some("source_user_privileges"),
+        "sproc" => Some("source_process_name"),
+        "spt" => Some("source_port"),
+        "src" => Some("source_address"),
+        "start" => Some("start_time"),
+        "suid" => Some("source_user_id"),
+        "suser" => Some("source_user_name"),
+        _ => None,
+    }
+}
+
+/// LEEF attributes that mean the same as a CEF key get the same name.
+fn leef_name(key: &str) -> Option<&'static str> {
+    match key {
+        "src" => Some("source_address"),
+        "dst" => Some("destination_address"),
+        "srcPort" => Some("source_port"),
+        "dstPort" => Some("destination_port"),
+        "srcMAC" => Some("source_mac_address"),
+        "dstMAC" => Some("destination_mac_address"),
+        "proto" => Some("transport_protocol"),
+        _ => None,
+    }
+}
+
+/// ArcSight Common Event Format, optionally behind a syslog header.
+///
+/// The seven header fields become `cef_version`, `device_vendor`, `device_product`,
+/// `device_version`, `signature_id`, `name` and `severity`, a number unless given as
+/// `Low`, `High` and so on. Extension keys with a documented full name are renamed to
+/// it, `src` to `source_address` for instance, and the rest are snake cased.
+/// `rt` also gives the `@timestamp` pair when it is in epoch milliseconds or
+/// `MMM dd yyyy HH:mm:ss` form.
+#[derive(Debug, Default)]
+pub struct CefParser;
+
+impl LogParser for CefParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let start = s.find("CEF:").ok_or(LogError::RegexParseError)?;
+        let (header, extension) = split_header(&s[start + 4..], 7).ok_or(LogError::RegexParseError)?;
+
+        let mut m = Map::new();
+        m.insert("cef_version".to_owned(), int(&header[0])?);
+        m.insert("device_vendor".to_owned(), Value::from(header[1].as_str()));
+        m.insert("device_product".to_owned(), Value::from(header[2].as_str()));
+        m.insert("device_version".to_owned(), Value::from(header[3].as_str()));
+        m.insert("signature_id".to_owned(), Value::from(header[4].as_str()));
+        m.insert("name".to_owned(), Value::from(header[5].as_str()));
+        m.insert("severity".to_owned(), match header[6].parse::<i64>() {
+            Ok(n) => Value::from(n),
+            Err(_) => Value::from(header[6].as_str()),
+        });
+
+        for (key, value) in split_extension(extension, ' ') {
+            let name = cef_name(&key).map(|n| n.to_owned()).unwrap_or_else(|| snake_case(&key));
+            let v = match name.as_str() {
+                "base_event_count" | "destination_port" | "source_port" | "bytes_in" | "bytes_out"
+                | "file_size" | "destination_process_id" | "source_process_id" | "device_process_id" => int(&value)?,
+                "device_receipt_time" => {
+                    if let Some(time) = event_time(&value) {
+                        insert_timestamp(&mut m, &time);
+                    }
+                    Value::from(value)
+                }
+                _ => Value::from(value),
+            };
+            m.insert(name, v);
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+/// IBM QRadar Log Event Extended Format, versions 1.0 and 2.0, optionally behind a
+/// syslog header.
+///
+/// The header becomes `leef_version`, `device_vendor`, `device_product`,
+/// `device_version` and `event_id`. Attributes are split on tabs, or on the delimiter
+/// a 2.0 header names, and snake cased, except that those CEF also has take its
+/// name. `devTime` gives the `@timestamp` pair as `rt` does for CEF.
+#[derive(Debug, Default)]
+pub struct LeefParser;
+
+impl LogParser for LeefParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let start = s.find("LEEF:").ok_or(LogError::RegexParseError)?;
+        let (version, _) = split_header(&s[start + 5..], 1).ok_or(LogError::RegexParseError)?;
+        let header_len = if version[0].starts_with('1') { 5 } else { 6 };
+        let (header, attributes) = split_header(&s[start + 5..], header_len).ok_or(LogError::RegexParseError)?;
+        let delimiter = match header.get(5) {
+            Some(d) => leef_delimiter(d)?,
+            None => '\t',
+        };
+
+        let mut m = Map::new();
+        m.insert("leef_version".to_owned(), Value::from(header[0].as_str()));
+        m.insert("device_vendor".to_owned(), Value::from(header[1].as_str()));
+        m.insert("device_product".to_owned(), Value::from(header[2].as_str()));
+        m.insert("device_version".to_owned(), Value::from(header[3].as_str()));
+        m.insert("event_id".to_owned(), Value::from(header[4].as_str()));
+
+        for (key, value) in split_extension(attributes, delimiter) {
+            let name = leef_name(&key).map(|n| n.to_owned()).unwrap_or_else(|| snake_case(&key));
+            let v = match name.as_str() {
+                "source_port" | "destination_port" | "src_bytes" | "dst_bytes" | "src_packets"
+                | "dst_packets" | "total_packets" | "sev" => int(&value)?,
+                "dev_time" => {
+                    if let Some(time) = event_time(&value) {
+                        insert_timestamp(&mut m, &time);
+                    }
+                    Value::from(value)
+                }
+                _ => Value::from(value),
+            };
+            m.insert(name, v);
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+/// The attribute delimiter of a LEEF 2.0 header: a character, or its code in hex
+/// such as `x09` or `0x09`. Empty means tab. A bad one fails only its line.
+fn leef_delimiter(s: &str) -> Result<char, LogError> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok('\t'),
+        (Some(c), None) => Ok(c),
+        _ => {
+            let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix('x')).ok_or(LogError::RegexParseError)?;
+            u32::from_str_radix(hex, 16).ok().and_then(std::char::from_u32).ok_or(LogError::RegexParseError)
+        }
+    }
+}
+
+/// Splits `n` `|` terminated header fields off `s`, unescaping `\|` and `\\`, and
+/// returns them with the rest of the line.
+fn split_header(s: &str, n: usize) -> Option<(Vec<String>, &str)> {
+    let mut fields = Vec::with_capacity(n);
+    let mut field = String::new();
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            if c != '|' && c != '\\' {
+                field.push('\\');
+            }
+            field.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '|' {
+            fields.push(std::mem::take(&mut field));
+            if fields.len() == n {
+                return Some((fields, &s[i + 1..]));
+            }
+        } else {
+            field.push(c);
+        }
+    }
+    None
+}
+
+/// Splits `key=value` pairs separated by `separator`. CEF values may contain spaces,
+/// so a value runs up to the next unescaped `=` preceded by a word, which starts the
+/// next key.
+fn split_extension(s: &str, separator: char) -> Vec<(String, String)> {
+    // Where each key starts and the offset of its `=`.
+    let mut keys: Vec<(usize, usize)> = Vec::new();
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '=' if !escaped => {
+                let start = s[..i].rfind(separator).map_or(0, |j| j + separator.len_utf8());
+                let key = &s[start..i];
+                let after_previous = keys.last().map_or(true, |&(_, eq)| start > eq);
+                if after_previous && !key.is_empty()
+                    && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-') {
+                    keys.push((start, i));
+                }
+            }
+            _ => escaped = false,
+        }
+    }
+
+    keys.iter().enumerate()
+        .map(|(n, &(start, eq))| {
+            let end = keys.get(n + 1).map_or(s.len(), |&(next, _)| next);
+            let value = s[eq + 1..end].trim_end_matches(|c: char| c == separator || c == '\r' || c == '\n');
+            (s[start..eq].to_owned(), unescape(value))
+        })
+        .collect()
+}
+
+/// Undoes the `\=`, `\|`, `\\`, `\n` and `\r` escapes of extension values.
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut escaped = false;
+    for c in s.chars() {
+        if escaped {
+            match c {
+                '=' | '|' | '\\' => out.push(c),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                _ => {
+                    out.push('\\');
+                    out.push(c);
+                }
+            }
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else {
+            out.push(c);
+        }
+    }
+    if escaped {
+        out.push('\\');
+    }
+    out
+}
+
+/// `srcPort` to `src_port`.
+fn snake_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in s.chars() {
+        if c.is_ascii_uppercase() && previous.map_or(false, |p| p.is_ascii_lowercase() || p.is_ascii_digit()) {
+            out.push('_');
+        }
+        out.push(c.to_ascii_lowercase());
+        previous = Some(c);
+    }
+    out.replace(|c: char| c == '.' || c == '-', "_")
+}
+
+/// Epoch milliseconds or `MMM dd yyyy HH:mm:ss[.SSS]`, taken as UTC.
+fn event_time(s: &str) -> Option<DateTime<Utc>> {
+    if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse::<i64>().ok().and_then(|ms| from_epoch_millis(ms).ok());
+    }
+    NaiveDateTime::parse_from_str(s, "%b %d %Y %H:%M:%S%.f").ok()
+        .map(|t| Utc.from_utc_datetime(&t))
+}
+
+#[test]
+fn cef_test() {
+    let data = r#"Sep 19 08:26:10 host CEF:0|Security|threat\|manager|1.0|100|worm successfully stopped|10|src=10.0.0.1 dst=2.1.2.2 spt=1232 msg=Detected a threat. No action needed a\=b c\\d rt=1505809570123 cs1Label=Rule Name cs1=Block all"#;
+    let a = CefParser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["cef_version"], 0);
+    assert_eq!(a["device_vendor"], "Security");
+    assert_eq!(a["device_product"], "threat|manager");
+    assert_eq!(a["signature_id"], "100");
+    assert_eq!(a["name"], "worm successfully stopped");
+    assert_eq!(a["severity"], 10);
+    assert_eq!(a["source_address"], "10.0.0.1");
+    assert_eq!(a["destination_address"], "2.1.2.2");
+    assert_eq!(a["source_port"], 1232);
+    assert_eq!(a["message"], r"Detected a threat. No action needed a=b c\d");
+    assert_eq!(a["@timestamp_utc"], "2017-09-19T08:26:10.123+00:00");
+    assert_eq!(a["cs1_label"], "Rule Name");
+    assert_eq!(a["cs1"], "Block all");
+
+    let a = CefParser.parse(b"CEF:0|Vendor|Product|1|sig|name|High|url=http://a/?x=1 rt=Sep 19 2017 08:26:10").unwrap();
+    assert_eq!(a["severity"], "High");
+    assert_eq!(a["url"], "http://a/?x=1");
+    assert_eq!(a["@timestamp_utc"], "2017-09-19T08:26:10+00:00");
+
+    assert!(CefParser.parse(b"CEF:0|Vendor|Product").is_err());
+}
+
+#[test]
+fn leef_test() {
+    let data = "LEEF:1.0|Microsoft|MSExchange|4.0 SP1|15345|src=192.0.2.0\tdst=172.50.123.1\tsrcPort=1234\tusrName=joe\\=smith\tdevTime=1505809570123";
+    let a = LeefParser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["leef_version"], "1.0");
+    assert_eq!(a["device_product"], "MSExchange");
+    assert_eq!(a["event_id"], "15345");
+    assert_eq!(a["source_address"], "192.0.2.0");
+    assert_eq!(a["source_port"], 1234);
+    assert_eq!(a["usr_name"], "joe=smith");
+    assert_eq!(a["@timestamp_utc"], "2017-09-19T08:26:10.123+00:00");
+
+    let a = LeefParser.parse(b"<13>Sep 19 08:26:10 host LEEF:2.0|Lancope|StealthWatch|1.0|41|^|src=10.0.1.8^dst=10.0.0.5^sev=5^cat=Port Scan").unwrap();
+    assert_eq!(a["destination_address"], "10.0.0.5");
+    assert_eq!(a["sev"], 5);
+    assert_eq!(a["cat"], "Port Scan");
+
+    let a = LeefParser.parse(b"LEEF:2.0|Vendor|Product|1.0|41|x7C|src=10.0.1.8|dst=10.0.0.5").unwrap();
+    assert_eq!(a["destination_address"], "10.0.0.5");
+
+    match LeefParser.parse(b"LEEF:2.0|Vendor|Product|1.0|41|xZZ|src=10.0.1.8") {
+        Err(LogError::RegexParseError) => (),
+        x => panic!("unexpected: {:?}", x),
+    }
+}
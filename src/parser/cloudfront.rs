@@ -0,0 +1,127 @@
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{insert_timestamp, LogParser, RecordContext};
+use super::fields::{float, int};
+use super::request::percent_decode;
+
+/// Columns of a standard log as currently documented by AWS.
+pub const FIELDS: &str = "date time x-edge-location sc-bytes c-ip cs-method cs(Host) cs-uri-stem sc-status \
+    cs(Referer) cs(User-Agent) cs-uri-query cs(Cookie) x-edge-result-type x-edge-request-id x-host-header \
+    cs-protocol cs-bytes time-taken x-forwarded-for ssl-protocol ssl-cipher x-edge-response-result-type \
+    cs-protocol-version fle-status fle-encrypted-fields c-port time-to-first-byte x-edge-detailed-result-type \
+    sc-content-type sc-content-len sc-range-start sc-range-end";
+
+/// Amazon CloudFront standard (access) log.
+///
+/// Columns are named by `CLOUDFRONT_FIELDS`, or the documented list by default, or by
+/// a `#Fields:` directive earlier in the same record. Names are snake cased, so
+/// `cs(User-Agent)` becomes `cs_user_agent`, values are percent-decoded and `date`
+/// and `time` are merged into `@timestamp`.
+#[derive(Debug)]
+pub struct CloudFrontParser {
+    fields: Vec<String>,
+}
+
+impl Default for CloudFrontParser {
+    fn default() -> CloudFrontParser {
+        CloudFrontParser::new(FIELDS)
+    }
+}
+
+impl CloudFrontParser {
+    pub fn new(fields: &str) -> CloudFrontParser {
+        CloudFrontParser { fields: split_fields(fields) }
+    }
+
+    pub fn from_env() -> CloudFrontParser {
+        match std::env::var("CLOUDFRONT_FIELDS") {
+            Ok(fields) => CloudFrontParser::new(&fields),
+            Err(_) => CloudFrontParser::default(),
+        }
+    }
+}
+
+fn split_fields(s: &str) -> Vec<String> {
+    s.split_whitespace().map(|f| f.to_owned()).collect()
+}
+
+fn field_name(s: &str) -> String {
+    s.to_lowercase().replace(')', "").replace(|c: char| c == '(' || c == '-', "_")
+}
+
+fn field_value(name: &str, s: &str) -> Result<Value, LogError> {
+    match name {
+        "sc-bytes" | "sc-status" | "cs-bytes" | "c-port" | "sc-content-len"
+        | "sc-range-start" | "sc-range-end" => int(s),
+        "time-taken" | "time-to-first-byte" => float(s),
+        _ => Ok(Value::from(percent_decode(s))),
+    }
+}
+
+impl LogParser for CloudFrontParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        self.parse_with_context(data, &RecordContext::default())
+    }
+
+    fn parse_with_context(&self, data: &[u8], ctx: &RecordContext) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        if let Some(fields) = s.strip_prefix("#Fields:") {
+            *ctx.header_fields.borrow_mut() = Some(split_fields(fields));
+            return Ok(Value::Null);
+        }
+        if s.starts_with('#') {
+            return Ok(Value::Null);
+        }
+
+        let header_fields = ctx.header_fields.borrow();
+        let fields = header_fields.as_ref().unwrap_or(&self.fields);
+        let xs = s.trim_end_matches(|c: char| c == '\r' || c == '\n').split('\t').collect::<Vec<&str>>();
+        if xs.len() != fields.len() {
+            return Err(LogError::RegexParseError);
+        }
+
+        let mut m = Map::new();
+        let (mut date, mut time) = (None, None);
+        for (name, &x) in fields.iter().zip(&xs) {
+            match name.as_str() {
+                "date" => date = Some(x),
+                "time" => time = Some(x),
+                _ => {
+                    m.insert(field_name(name), field_value(name, x)?);
+                }
+            }
+        }
+        if let (Some(date), Some(time)) = (date, time) {
+            let t = NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S")?;
+            insert_timestamp(&mut m, &Utc.from_utc_datetime(&t));
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+#[test]
+fn cloudfront_test() {
+    let parser = CloudFrontParser::default();
+    assert!(parser.parse(b"#Version: 1.0").unwrap().is_null());
+
+    let data = "2019-12-04\t21:02:31\tLAX1\t392\t192.0.2.100\tGET\td111111abcdef8.cloudfront.net\t/index.html\t200\t-\tMozilla/5.0%20(Windows%20NT%2010.0;%20Win64;%20x64)\t-\t-\tHit\tSOX4xwn4XV6Q4rgb7XiVGOHms_BGlTAC4KyHmureZmBNrjGdRLiNIQ==\td111111abcdef8.cloudfront.net\thttps\t23\t0.001\t-\tTLSv1.2\tECDHE-RSA-AES128-GCM-SHA256\tHit\tHTTP/2.0\t-\t-\t11040\t0.001\tHit\ttext/html\t78\t-\t-";
+    let a = parser.parse(data.as_bytes()).unwrap();
+    assert_eq!(a["@timestamp_utc"], "2019-12-04T21:02:31+00:00");
+    assert_eq!(a["sc_bytes"], 392);
+    assert_eq!(a["cs_host"], "d111111abcdef8.cloudfront.net");
+    assert_eq!(a["cs_user_agent"], "Mozilla/5.0 (Windows NT 10.0; Win64; x64)");
+    assert_eq!(a["time_taken"], 0.001);
+    assert!(a["sc_range_start"].is_null());
+
+    let ctx = RecordContext::default();
+    assert!(parser.parse_with_context(b"#Fields: date time cs-uri-stem", &ctx).unwrap().is_null());
+    let a = parser.parse_with_context(b"2019-12-04\t21:02:31\t/a%2Fb", &ctx).unwrap();
+    assert_eq!(a["cs_uri_stem"], "/a/b");
+    assert!(parser.parse_with_context(data.as_bytes(), &ctx).is_err());
+
+    // The header only applies to the record it came in.
+    assert!(parser.parse(b"2019-12-04\t21:02:31\t/a%2Fb").is_err());
+    assert_eq!(parser.parse(data.as_bytes()).unwrap()["sc_bytes"], 392);
+}
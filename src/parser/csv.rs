@@ -0,0 +1,198 @@
+use chrono::DateTime;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{insert_timestamp, LogParser};
+use super::fields::{float, int};
+
+/// How a column is turned into JSON. Empty and `-` values of the numeric types are null.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    Str,
+    Int,
+    Float,
+    Bool,
+    /// RFC3339; the first such column also gives the `@timestamp` pair.
+    Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub kind: ColumnType,
+}
+
+impl Column {
+    /// `name` or `name:type`, where type is `string`, `int`, `float`, `bool` or `timestamp`.
+    fn new(s: &str) -> Result<Column, LogError> {
+        let mut parts = s.trim().splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let kind = match parts.next() {
+            None | Some("string") => ColumnType::Str,
+            Some("int") => ColumnType::Int,
+            Some("float") => ColumnType::Float,
+            Some("bool") => ColumnType::Bool,
+            Some("timestamp") => ColumnType::Timestamp,
+            Some(t) => return Err(LogError::ConfigError(format!("unknown column type: {}", t))),
+        };
+        if name.is_empty() {
+            return Err(LogError::ConfigError(format!("invalid column: {}", s)));
+        }
+        Ok(Column { name: name.to_owned(), kind })
+    }
+
+    fn value(&self, s: &str) -> Result<Value, LogError> {
+        match self.kind {
+            ColumnType::Str => Ok(Value::from(s)),
+            ColumnType::Int => int(s),
+            ColumnType::Float => float(s),
+            ColumnType::Bool => match s {
+                "true" | "TRUE" | "True" | "1" => Ok(Value::Bool(true)),
+                "false" | "FALSE" | "False" | "0" => Ok(Value::Bool(false)),
+                "" | "-" => Ok(Value::Null),
+                _ => Err(LogError::BoolParseError(self.name.clone(), s.to_owned())),
+            },
+            ColumnType::Timestamp => match s {
+                "" | "-" => Ok(Value::Null),
+                _ => Ok(Value::from(DateTime::parse_from_rfc3339(s)?.to_rfc3339())),
+            },
+        }
+    }
+}
+
+/// Delimited text such as CSV or TSV with a fixed column schema.
+///
+/// `CSV_COLUMNS` lists the columns as `name[:type]`, comma separated and required.
+/// `CSV_DELIMITER` is a single character or `tab` (default `,`) and `CSV_QUOTE` the
+/// quote character (default `"`), doubled to escape it inside a quoted field.
+/// A line with another number of fields fails with `ColumnCountError`, and a header
+/// line repeating the column names is skipped.
+#[derive(Debug)]
+pub struct CsvParser {
+    pub delimiter: char,
+    pub quote: char,
+    pub columns: Vec<Column>,
+}
+
+impl CsvParser {
+    pub fn new(columns: &str) -> Result<CsvParser, LogError> {
+        Ok(CsvParser {
+            delimiter: ',',
+            quote: '"',
+            columns: columns.split(',').map(Column::new).collect::<Result<Vec<Column>, LogError>>()?,
+        })
+    }
+
+    pub fn from_env() -> Result<CsvParser, LogError> {
+        let columns = std::env::var("CSV_COLUMNS")
+            .map_err(|_| LogError::ConfigError("CSV_COLUMNS is required for LOG_FORMAT=csv".to_owned()))?;
+        let mut parser = CsvParser::new(&columns)?;
+        if let Ok(s) = std::env::var("CSV_DELIMITER") {
+            parser.delimiter = single_char("CSV_DELIMITER", if s == "tab" { "\t" } else { &s })?;
+        }
+        if let Ok(s) = std::env::var("CSV_QUOTE") {
+            parser.quote = single_char("CSV_QUOTE", &s)?;
+        }
+        Ok(parser)
+    }
+
+    /// Splits a line into fields, unquoting quoted ones. An unterminated quote is an error.
+    fn split(&self, s: &str) -> Result<Vec<String>, LogError> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut chars = s.chars().peekable();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            if quoted {
+                if c != self.quote {
+                    field.push(c);
+                } else if chars.peek() == Some(&self.quote) {
+                    field.push(c);
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            } else if c == self.quote && field.is_empty() {
+                quoted = true;
+            } else if c == self.delimiter {
+                fields.push(std::mem::take(&mut field));
+            } else {
+                field.push(c);
+            }
+        }
+        if quoted {
+            return Err(LogError::RegexParseError);
+        }
+        fields.push(field);
+        Ok(fields)
+    }
+}
+
+fn single_char(name: &str, s: &str) -> Result<char, LogError> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(LogError::ConfigError(format!("{} must be a single character: {}", name, s))),
+    }
+}
+
+impl LogParser for CsvParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let xs = self.split(s.trim_end_matches(|c: char| c == '\r' || c == '\n'))?;
+        if xs.len() != self.columns.len() {
+            return Err(LogError::ColumnCountError(self.columns.len(), xs.len()));
+        }
+        if self.columns.iter().zip(&xs).all(|(column, x)| column.name == *x) {
+            return Ok(Value::Null);
+        }
+
+        let mut m = Map::new();
+        let mut has_timestamp = false;
+        for (column, x) in self.columns.iter().zip(&xs) {
+            if column.kind == ColumnType::Timestamp && !has_timestamp && !x.is_empty() && x != "-" {
+                insert_timestamp(&mut m, &DateTime::parse_from_rfc3339(x)?);
+                has_timestamp = true;
+            }
+            m.insert(column.name.clone(), column.value(x)?);
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+#[test]
+fn csv_test() {
+    let parser = CsvParser::new("time:timestamp,host,status:int,latency:float,cached:bool,path").unwrap();
+    let a = parser.parse(br#"2017-12-14T22:16:45+09:00,web-1,200,0.125,true,"/a,b ""c""""#).unwrap();
+
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+    assert_eq!(a["time"], "2017-12-14T22:16:45+09:00");
+    assert_eq!(a["host"], "web-1");
+    assert_eq!(a["status"], 200);
+    assert_eq!(a["latency"], 0.125);
+    assert_eq!(a["cached"], true);
+    assert_eq!(a["path"], "/a,b \"c\"");
+
+    let a = parser.parse(b"-,web-1,,,,").unwrap();
+    assert!(a.get("@timestamp").is_none());
+    assert!(a["status"].is_null());
+
+    assert!(parser.parse(b"time,host,status,latency,cached,path").unwrap().is_null());
+    match parser.parse(b"2017-12-14T22:16:45+09:00,web-1,200") {
+        Err(LogError::ColumnCountError(6, 3)) => (),
+        x => panic!("unexpected: {:?}", x),
+    }
+    match parser.parse(b"-,web-1,200,0.125,maybe,/") {
+        Err(LogError::BoolParseError(ref column, ref value)) => assert_eq!((column.as_str(), value.as_str()), ("cached", "maybe")),
+        x => panic!("unexpected: {:?}", x),
+    }
+    assert!(CsvParser::new("a,b:date").is_err());
+}
+
+#[test]
+fn tsv_test() {
+    let parser = CsvParser { delimiter: '\t', quote: '\'', ..CsvParser::new("user,count:int").unwrap() };
+    let a = parser.parse(b"'o''brien, jr'\t3\r\n").unwrap();
+    assert_eq!(a["user"], "o'brien, jr");
+    assert_eq!(a["count"], 3);
+}
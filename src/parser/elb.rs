@@ -0,0 +1,79 @@
+use chrono::DateTime;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{insert_timestamp, LogParser};
+use super::apache::host_type;
+use super::fields::{address, float, int, split_fields, string};
+use super::request::parse_request_line;
+
+/// Fields every Classic Load Balancer log entry has, up to `ssl_protocol`.
+const REQUIRED_FIELDS: usize = 15;
+
+/// AWS Classic Load Balancer access log.
+///
+/// Fields that mean the same as in the Apache access log use its names: the client is
+/// `host`, the load balancer's status is `response` and the bytes sent are `bytes`.
+/// TCP listeners log `-` for everything HTTP specific, which becomes null.
+#[derive(Debug, Default)]
+pub struct ElbParser;
+
+impl LogParser for ElbParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let xs = split_fields(&s);
+        if xs.len() < REQUIRED_FIELDS {
+            return Err(LogError::RegexParseError);
+        }
+
+        let mut m = Map::new();
+        insert_timestamp(&mut m, &DateTime::parse_from_rfc3339(xs[0])?);
+        m.insert("elb".to_owned(), string(xs[1]));
+        let (host, client_port) = address(xs[2])?;
+        if let Some(h) = host.as_str() {
+            m.insert("host_type".to_owned(), serde_json::to_value(host_type(h)?)?);
+        }
+        m.insert("host".to_owned(), host);
+        m.insert("client_port".to_owned(), client_port);
+        let (backend_ip, backend_port) = address(xs[3])?;
+        m.insert("backend_ip".to_owned(), backend_ip);
+        m.insert("backend_port".to_owned(), backend_port);
+        m.insert("request_processing_time".to_owned(), float(xs[4])?);
+        m.insert("backend_processing_time".to_owned(), float(xs[5])?);
+        m.insert("response_processing_time".to_owned(), float(xs[6])?);
+        m.insert("response".to_owned(), int(xs[7])?);
+        m.insert("backend_status_code".to_owned(), int(xs[8])?);
+        m.insert("received_bytes".to_owned(), int(xs[9])?);
+        m.insert("bytes".to_owned(), int(xs[10])?);
+        m.insert("request".to_owned(), string(xs[11]));
+        if let Value::Object(r) = serde_json::to_value(parse_request_line(xs[11]))? {
+            m.extend(r);
+        }
+        m.insert("user_agent".to_owned(), string(xs[12]));
+        m.insert("ssl_cipher".to_owned(), string(xs[13]));
+        m.insert("ssl_protocol".to_owned(), string(xs[14]));
+        Ok(Value::Object(m))
+    }
+}
+
+#[test]
+fn elb_test() {
+    let data = r#"2015-05-13T23:39:43.945958Z my-loadbalancer 192.168.131.39:2817 10.0.0.1:80 0.000073 0.001048 0.000057 200 200 0 29 "GET http://www.example.com:80/ HTTP/1.1" "curl/7.38.0" - -"#;
+    let a = ElbParser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["@timestamp_utc"], "2015-05-13T23:39:43.945958+00:00");
+    assert_eq!(a["host"], "192.168.131.39");
+    assert_eq!(a["host_type"], "ipv4");
+    assert_eq!(a["backend_port"], 80);
+    assert_eq!(a["backend_processing_time"], 0.001048);
+    assert_eq!(a["response"], 200);
+    assert_eq!(a["bytes"], 29);
+    assert_eq!(a["path"], "http://www.example.com:80/");
+    assert_eq!(a["user_agent"], "curl/7.38.0");
+
+    let data = r#"2015-05-13T23:39:43.945958Z my-loadbalancer 192.168.131.39:2817 10.0.0.1:80 0.001069 0.000028 0.000041 - - 82 305 "- - - " "-" - -"#;
+    let a = ElbParser.parse(data.as_bytes()).unwrap();
+    assert!(a["response"].is_null());
+    assert!(a["method"].is_null());
+    assert_eq!(a["received_bytes"], 82);
+}
@@ -0,0 +1,233 @@
+use std::net::Ipv6Addr;
+
+use chrono::prelude::*;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{insert_timestamp, offset_from_env, LogParser};
+use super::fields::address;
+use super::request::parse_request_line;
+
+/// Apache httpd `error_log` lines, 2.2 and 2.4 style, and nginx `error_log` lines.
+///
+/// Apache: `[Wed Oct 11 14:32:52.123456 2017] [core:error] [pid 1234:tid 5678]
+/// [client 1.2.3.4:5678] AH00124: message, referer: http://...` gives `module`,
+/// `level`, `pid`, `tid`, `client_ip`, `client_port`, `error_code`, `message` and
+/// `referer`. nginx: `2017/10/11 14:32:52 [error] 1234#5678: *99 message, client:
+/// 1.2.3.4, server: ..., request: "GET / HTTP/1.1", host: "..."` gives `level`, `pid`,
+/// `tid`, `connection_id` and `message`, plus the trailing context with `request`
+/// split like an access log's and `host` renamed `http_host`.
+///
+/// Neither writes a time zone; times are in `ERROR_LOG_TIMEZONE`, see `offset_from_env`.
+#[derive(Debug)]
+pub struct ErrorLogParser {
+    pub offset: FixedOffset,
+    apache_message: Regex,
+    nginx: Regex,
+}
+
+impl Default for ErrorLogParser {
+    fn default() -> ErrorLogParser {
+        ErrorLogParser {
+            offset: FixedOffset::east_opt(0).unwrap(),
+            apache_message: Regex::new(r"^(?:(AH\d{5}): )?(.*?)(?:, referer: (.*))?$").unwrap(),
+            nginx: Regex::new(r"^(\d{4}/\d{2}/\d{2} \d{2}:\d{2}:\d{2}) \[(\w+)\] (\d+)#(\d+): (?:\*(\d+) )?(.*)$").unwrap(),
+        }
+    }
+}
+
+impl ErrorLogParser {
+    pub fn from_env() -> Result<ErrorLogParser, LogError> {
+        Ok(ErrorLogParser { offset: offset_from_env("ERROR_LOG_TIMEZONE")?, ..ErrorLogParser::default() })
+    }
+
+    fn local_time(&self, s: &str, format: &str) -> Result<DateTime<FixedOffset>, LogError> {
+        let t = NaiveDateTime::parse_from_str(s, format)?;
+        self.offset.from_local_datetime(&t).single().ok_or(LogError::RegexParseError)
+    }
+
+    fn parse_apache(&self, s: &str) -> Result<Map<String, Value>, LogError> {
+        let mut m = Map::new();
+        let mut rest = s;
+        let mut n = 0;
+        while let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').ok_or(LogError::RegexParseError)?;
+            let x = &r[..end];
+            if n == 0 {
+                insert_timestamp(&mut m, &self.local_time(x, "%a %b %d %H:%M:%S%.f %Y")?);
+            } else if n == 1 {
+                // `[core:error]` since 2.4, `[error]` before.
+                let (module, level) = match x.find(':') {
+                    Some(i) => (Value::from(&x[..i]), &x[i + 1..]),
+                    None => (Value::Null, x),
+                };
+                m.insert("module".to_owned(), module);
+                m.insert("level".to_owned(), Value::from(level));
+            } else if let Some(pid) = x.strip_prefix("pid ") {
+                let (pid, tid) = match pid.find(":tid ") {
+                    Some(i) => (&pid[..i], Some(&pid[i + 5..])),
+                    None => (pid, None),
+                };
+                m.insert("pid".to_owned(), Value::from(pid.parse::<u64>()?));
+                m.insert("tid".to_owned(), match tid {
+                    Some(tid) => Value::from(tid.parse::<u64>()?),
+                    None => Value::Null,
+                });
+            } else if let Some(client) = x.strip_prefix("client ") {
+                let (ip, port) = client_address(client)?;
+                m.insert("client_ip".to_owned(), ip);
+                m.insert("client_port".to_owned(), port);
+            } else {
+                break;
+            }
+            rest = r[end + 1..].strip_prefix(' ').unwrap_or(&r[end + 1..]);
+            n += 1;
+        }
+        if n < 2 {
+            return Err(LogError::RegexParseError);
+        }
+
+        let xs = self.apache_message.captures(rest).ok_or(LogError::RegexParseError)?;
+        m.insert("error_code".to_owned(), xs.get(1).map(|x| Value::from(x.as_str())).unwrap_or(Value::Null));
+        m.insert("message".to_owned(), Value::from(&xs[2]));
+        m.insert("referer".to_owned(), xs.get(3).map(|x| Value::from(x.as_str())).unwrap_or(Value::Null));
+        Ok(m)
+    }
+
+    fn parse_nginx(&self, s: &str) -> Result<Map<String, Value>, LogError> {
+        let xs = self.nginx.captures(s).ok_or(LogError::RegexParseError)?;
+
+        let mut m = Map::new();
+        insert_timestamp(&mut m, &self.local_time(&xs[1], "%Y/%m/%d %H:%M:%S")?);
+        m.insert("level".to_owned(), Value::from(&xs[2]));
+        m.insert("pid".to_owned(), Value::from(xs[3].parse::<u64>()?));
+        m.insert("tid".to_owned(), Value::from(xs[4].parse::<u64>()?));
+        m.insert("connection_id".to_owned(), match xs.get(5) {
+            Some(x) => Value::from(x.as_str().parse::<u64>()?),
+            None => Value::Null,
+        });
+
+        let message = &xs[6];
+        let (message, context) = match message.find(", client: ") {
+            Some(i) => (&message[..i], &message[i + 2..]),
+            None => (message, ""),
+        };
+        m.insert("message".to_owned(), Value::from(message));
+        for (key, value) in split_context(context) {
+            match key {
+                "client" => {
+                    m.insert("client_ip".to_owned(), Value::from(value));
+                }
+                "request" => {
+                    m.insert("request".to_owned(), Value::from(value));
+                    if let Value::Object(r) = serde_json::to_value(parse_request_line(value))? {
+                        m.extend(r);
+                    }
+                }
+                "host" => {
+                    m.insert("http_host".to_owned(), Value::from(value));
+                }
+                "referrer" => {
+                    m.insert("referer".to_owned(), Value::from(value));
+                }
+                _ => {
+                    m.insert(key.to_owned(), Value::from(value));
+                }
+            }
+        }
+        Ok(m)
+    }
+}
+
+impl LogParser for ErrorLogParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let s = s.trim_end_matches(|c: char| c == '\r' || c == '\n');
+        let m = if s.starts_with('[') { self.parse_apache(s)? } else { self.parse_nginx(s)? };
+        Ok(Value::Object(m))
+    }
+}
+
+/// Apache writes `ip:port`, or only the ip before 2.4.
+fn client_address(s: &str) -> Result<(Value, Value), LogError> {
+    if s.parse::<Ipv6Addr>().is_ok() {
+        return Ok((Value::from(s), Value::Null));
+    }
+    address(s)
+}
+
+/// Splits nginx's `client: 1.2.3.4, server: example.com, request: "GET / HTTP/1.1"`.
+fn split_context(s: &str) -> Vec<(&str, &str)> {
+    let mut pairs = Vec::new();
+    let mut rest = s;
+    while let Some(i) = rest.find(": ") {
+        let key = &rest[..i];
+        let r = &rest[i + 2..];
+        let (value, next) = match r.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = r.find(", ").unwrap_or(r.len());
+                (&r[..end], &r[end..])
+            }
+        };
+        pairs.push((key, value));
+        rest = next.strip_prefix(", ").unwrap_or(next);
+    }
+    pairs
+}
+
+#[test]
+fn apache_error_log_test() {
+    let parser = ErrorLogParser { offset: FixedOffset::east_opt(9 * 3600).unwrap(), ..ErrorLogParser::default() };
+    let data = "[Wed Oct 11 14:32:52.123456 2017] [core:error] [pid 1234:tid 5678] [client 1.2.3.4:5678] AH00124: Request exceeded the limit of 10 internal redirects, referer: http://example.com/";
+    let a = parser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["@timestamp"], "2017-10-11T14:32:52.123456+09:00");
+    assert_eq!(a["@timestamp_utc"], "2017-10-11T05:32:52.123456+00:00");
+    assert_eq!(a["module"], "core");
+    assert_eq!(a["level"], "error");
+    assert_eq!(a["pid"], 1234);
+    assert_eq!(a["tid"], 5678);
+    assert_eq!(a["client_ip"], "1.2.3.4");
+    assert_eq!(a["client_port"], 5678);
+    assert_eq!(a["error_code"], "AH00124");
+    assert_eq!(a["message"], "Request exceeded the limit of 10 internal redirects");
+    assert_eq!(a["referer"], "http://example.com/");
+
+    let a = parser.parse(b"[Wed Oct 11 14:32:52 2017] [error] [client 2001:db8::1] File does not exist: /var/www/favicon.ico").unwrap();
+    assert!(a["module"].is_null());
+    assert_eq!(a["client_ip"], "2001:db8::1");
+    assert!(a["client_port"].is_null());
+    assert!(a["error_code"].is_null());
+    assert_eq!(a["message"], "File does not exist: /var/www/favicon.ico");
+
+    assert!(parser.parse(b"[Wed Oct 11 14:32:52 2017] no level").is_err());
+}
+
+#[test]
+fn nginx_error_log_test() {
+    let parser = ErrorLogParser::default();
+    let data = r#"2017/10/11 14:32:52 [error] 1234#5678: *99 open() "/usr/share/nginx/html/x" failed (2: No such file or directory), client: 1.2.3.4, server: example.com, request: "GET /x?a=1 HTTP/1.1", host: "example.com", referrer: "http://example.com/""#;
+    let a = parser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["@timestamp_utc"], "2017-10-11T14:32:52+00:00");
+    assert_eq!(a["level"], "error");
+    assert_eq!(a["pid"], 1234);
+    assert_eq!(a["tid"], 5678);
+    assert_eq!(a["connection_id"], 99);
+    assert_eq!(a["message"], r#"open() "/usr/share/nginx/html/x" failed (2: No such file or directory)"#);
+    assert_eq!(a["client_ip"], "1.2.3.4");
+    assert_eq!(a["server"], "example.com");
+    assert_eq!(a["path"], "/x");
+    assert_eq!(a["query_params"]["a"], "1");
+    assert_eq!(a["http_host"], "example.com");
+    assert_eq!(a["referer"], "http://example.com/");
+
+    let a = parser.parse(b"2017/10/11 14:32:52 [notice] 1#1: signal process started").unwrap();
+    assert!(a["connection_id"].is_null());
+    assert_eq!(a["message"], "signal process started");
+}
@@ -0,0 +1,101 @@
+//! Helpers for the space separated formats AWS services write, where a field may be
+//! `"quoted"` or `[bracketed]` and `-` stands for no value.
+
+use serde_json::Value;
+
+use error::LogError;
+
+/// Splits a line on single spaces, keeping quoted and bracketed fields whole and
+/// stripping their delimiters. `\"` does not end a quoted field.
+pub fn split_fields(s: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut rest = s.trim_end_matches(|c: char| c == '\r' || c == '\n');
+    while !rest.is_empty() {
+        let (field, next) = if let Some(r) = rest.strip_prefix('"') {
+            let end = quoted_end(r);
+            (&r[..end], r.get(end + 1..).unwrap_or(""))
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').unwrap_or(r.len());
+            (&r[..end], r.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        fields.push(field);
+        rest = next.strip_prefix(' ').unwrap_or(next);
+    }
+    fields
+}
+
+/// Byte offset of the closing quote in `s`, or its length when unterminated.
+fn quoted_end(s: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return i,
+            _ => escaped = false,
+        }
+    }
+    s.len()
+}
+
+pub fn string(s: &str) -> Value {
+    Value::from(s)
+}
+
+pub fn int(s: &str) -> Result<Value, LogError> {
+    match s {
+        "-" | "" => Ok(Value::Null),
+        _ => Ok(Value::from(s.parse::<i64>()?)),
+    }
+}
+
+pub fn float(s: &str) -> Result<Value, LogError> {
+    match s {
+        "-" | "" => Ok(Value::Null),
+        _ => Ok(Value::from(s.parse::<f64>()?)),
+    }
+}
+
+/// Splits `ip:port`, where the ip may be IPv6, into two values. `-` gives two nulls.
+pub fn address(s: &str) -> Result<(Value, Value), LogError> {
+    if s == "-" || s.is_empty() {
+        return Ok((Value::Null, Value::Null));
+    }
+    match s.rfind(':') {
+        Some(i) => Ok((Value::from(&s[..i]), int(&s[i + 1..])?)),
+        None => Ok((Value::from(s), Value::Null)),
+    }
+}
+
+/// Splits a list field such as `forward,redirect` into an array. `-` gives null.
+pub fn list(s: &str, separator: char) -> Value {
+    match s {
+        "-" | "" => Value::Null,
+        _ => Value::Array(s.split(separator).map(Value::from).collect()),
+    }
+}
+
+#[test]
+fn split_fields_test() {
+    let xs = split_fields(r#"http 2018-07-02T22:23:00.186641Z "GET http://a/ HTTP/1.1" "say \"hi\"" [06/Feb/2019:00:00:38 +0000] - """#);
+    assert_eq!(xs, vec![
+        "http",
+        "2018-07-02T22:23:00.186641Z",
+        "GET http://a/ HTTP/1.1",
+        r#"say \"hi\""#,
+        "06/Feb/2019:00:00:38 +0000",
+        "-",
+        "",
+    ]);
+}
+
+#[test]
+fn address_test() {
+    let (ip, port) = address("2001:db8::1:2817").unwrap();
+    assert_eq!(ip, "2001:db8::1");
+    assert_eq!(port, 2817);
+    let (ip, port) = address("-").unwrap();
+    assert!(ip.is_null() && port.is_null());
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::LogParser;
+use super::fields::{float, int};
+
+/// The commonly used part of Logstash's `grok-patterns`, rewritten without the
+/// lookaround and atomic groups the `regex` crate lacks.
+pub const PATTERNS: &str = r#"
+USERNAME [a-zA-Z0-9._-]+
+USER %{USERNAME}
+EMAILLOCALPART [a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+(?:\.[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+)*
+EMAILADDRESS %{EMAILLOCALPART}@%{HOSTNAME}
+INT [+-]?[0-9]+
+BASE10NUM [+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)
+NUMBER %{BASE10NUM}
+BASE16NUM [+-]?(?:0[xX])?[0-9A-Fa-f]+
+POSINT \b[1-9][0-9]*\b
+NONNEGINT \b[0-9]+\b
+WORD \b\w+\b
+NOTSPACE \S+
+SPACE \s*
+DATA .*?
+GREEDYDATA .*
+QUOTEDSTRING "(?:\\.|[^\\"])*"|'(?:\\.|[^\\'])*'
+QS %{QUOTEDSTRING}
+UUID [A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}
+
+CISCOMAC (?:[A-Fa-f0-9]{4}\.){2}[A-Fa-f0-9]{4}
+WINDOWSMAC (?:[A-Fa-f0-9]{2}-){5}[A-Fa-f0-9]{2}
+COMMONMAC (?:[A-Fa-f0-9]{2}:){5}[A-Fa-f0-9]{2}
+MAC %{CISCOMAC}|%{WINDOWSMAC}|%{COMMONMAC}
+IPV6 (?:[0-9A-Fa-f]{1,4})?(?::[0-9A-Fa-f]{0,4}){2,7}(?:%[0-9A-Za-z]+)?
+IPV4 (?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])
+IP %{IPV6}|%{IPV4}
+HOSTNAME \b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?
+IPORHOST %{IP}|%{HOSTNAME}
+HOSTPORT %{IPORHOST}:%{POSINT}
+
+UNIXPATH (?:/[\w_%!$@:.,+~-]*)+
+WINPATH (?:[A-Za-z]+:|\\)(?:\\[^\\?*]*)+
+PATH %{UNIXPATH}|%{WINPATH}
+URIPROTO [A-Za-z][A-Za-z0-9+.-]+
+URIHOST %{IPORHOST}(?::%{POSINT})?
+URIPATH (?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_-]*)+
+URIPARAM \?[A-Za-z0-9$.+!*'|(){},~@#%&/=:;_?\[\]<>-]*
+URIPATHPARAM %{URIPATH}(?:%{URIPARAM})?
+URI %{URIPROTO}://(?:%{USER}(?::[^@]*)?@)?(?:%{URIHOST})?(?:%{URIPATHPARAM})?
+
+MONTH \b(?:[Jj]an(?:uary)?|[Ff]eb(?:ruary)?|[Mm]ar(?:ch)?|[Aa]pr(?:il)?|[Mm]ay|[Jj]un(?:e)?|[Jj]ul(?:y)?|[Aa]ug(?:ust)?|[Ss]ep(?:tember)?|[Oo]ct(?:ober)?|[Nn]ov(?:ember)?|[Dd]ec(?:ember)?)\b
+MONTHNUM 0?[1-9]|1[0-2]
+MONTHDAY 0[1-9]|[12][0-9]|3[01]|[1-9]
+DAY Mon(?:day)?|Tue(?:sday)?|Wed(?:nesday)?|Thu(?:rsday)?|Fri(?:day)?|Sat(?:urday)?|Sun(?:day)?
+YEAR (?:\d\d){1,2}
+HOUR 2[0123]|[01]?[0-9]
+MINUTE [0-5][0-9]
+SECOND (?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?
+TIME %{HOUR}:%{MINUTE}(?::%{SECOND})?
+ISO8601_TIMEZONE Z|[+-]%{HOUR}(?::?%{MINUTE})
+TIMESTAMP_ISO8601 %{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}(?::?%{SECOND})?%{ISO8601_TIMEZONE}?
+HTTPDATE %{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} %{INT}
+SYSLOGTIMESTAMP %{MONTH} +%{MONTHDAY} %{TIME}
+PROG [\x21-\x5a\x5c\x5e-\x7e]+
+SYSLOGPROG %{PROG:program}(?:\[%{POSINT:pid}\])?
+SYSLOGHOST %{IPORHOST}
+LOGLEVEL [Aa]lert|ALERT|[Tt]race|TRACE|[Dd]ebug|DEBUG|[Nn]otice|NOTICE|[Ii]nfo|INFO|[Ww]arn?(?:ing)?|WARN?(?:ING)?|[Ee]rr?(?:or)?|ERR?(?:OR)?|[Cc]rit?(?:ical)?|CRIT?(?:ICAL)?|[Ff]atal|FATAL|[Ss]evere|SEVERE|EMERG(?:ENCY)?|[Ee]merg(?:ency)?
+
+HTTPDUSER %{EMAILADDRESS}|%{USER}
+COMMONAPACHELOG %{IPORHOST:clientip} %{HTTPDUSER:ident} %{USER:auth} \[%{HTTPDATE:timestamp}\] "(?:%{WORD:verb} %{NOTSPACE:request}(?: HTTP/%{NUMBER:httpversion})?|%{DATA:rawrequest})" %{NUMBER:response:int} (?:%{NUMBER:bytes:int}|-)
+COMBINEDAPACHELOG %{COMMONAPACHELOG} %{QS:referrer} %{QS:agent}
+"#;
+
+/// Expansions nested deeper than this are taken to be a pattern including itself.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldKind {
+    Str,
+    Int,
+    Float,
+}
+
+#[derive(Debug)]
+struct Field {
+    name: String,
+    /// Name of the capture group, `f0`, `f1`, ..., as grok field names need not be
+    /// valid group names.
+    group: String,
+    kind: FieldKind,
+}
+
+/// Lines matching a Logstash grok expression given by `GROK_PATTERN`, such as
+/// `%{IPORHOST:clientip} %{WORD:method} %{NUMBER:bytes:int}`.
+///
+/// `%{NAME}` expands to pattern `NAME`, either from `PATTERNS` or from
+/// `GROK_PATTERNS`, one `NAME regex` definition per line, which may override it.
+/// `%{NAME:field}` captures the match as `field`, a string unless suffixed `:int`
+/// or `:float`. Fields in optional parts that did not take part in the match are
+/// left out.
+#[derive(Debug)]
+pub struct GrokParser {
+    regex: Regex,
+    fields: Vec<Field>,
+}
+
+impl GrokParser {
+    pub fn from_env() -> Result<GrokParser, LogError> {
+        let expression = std::env::var("GROK_PATTERN")
+            .map_err(|_| LogError::ConfigError("GROK_PATTERN is required for LOG_FORMAT=grok".to_owned()))?;
+        let patterns = std::env::var("GROK_PATTERNS").unwrap_or_default();
+        GrokParser::new(&expression, &patterns)
+    }
+
+    /// Compiles `expression` against the bundled patterns plus the definitions in `patterns`.
+    pub fn new(expression: &str, patterns: &str) -> Result<GrokParser, LogError> {
+        let mut library = HashMap::new();
+        load_patterns(&mut library, PATTERNS)?;
+        load_patterns(&mut library, patterns)?;
+
+        let mut compiler = Compiler {
+            reference: Regex::new(r"%\{(\w+)(?::([^:}]+))?(?::(\w+))?\}").unwrap(),
+            library,
+            fields: Vec::new(),
+        };
+        let pattern = compiler.expand(expression, 0)?;
+        let regex = Regex::new(&format!("^{}$", pattern))
+            .map_err(|e| LogError::ConfigError(format!("invalid grok pattern: {}", e)))?;
+        Ok(GrokParser { regex, fields: compiler.fields })
+    }
+}
+
+fn load_patterns(library: &mut HashMap<String, String>, s: &str) -> Result<(), LogError> {
+    for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let mut parts = line.splitn(2, char::is_whitespace);
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(pattern)) => {
+                library.insert(name.to_owned(), pattern.trim().to_owned());
+            }
+            _ => return Err(LogError::ConfigError(format!("invalid grok pattern definition: {}", line))),
+        }
+    }
+    Ok(())
+}
+
+struct Compiler {
+    reference: Regex,
+    library: HashMap<String, String>,
+    fields: Vec<Field>,
+}
+
+impl Compiler {
+    /// Replaces every `%{...}` in `s` by its regex, recursively.
+    fn expand(&mut self, s: &str, depth: usize) -> Result<String, LogError> {
+        if depth > MAX_DEPTH {
+            return Err(LogError::ConfigError(format!("grok pattern nested too deeply: {}", s)));
+        }
+
+        let mut out = String::new();
+        let mut last = 0;
+        let references = self.reference.captures_iter(s).collect::<Vec<_>>();
+        for xs in references {
+            let whole = xs.get(0).unwrap();
+            out.push_str(&s[last..whole.start()]);
+            last = whole.end();
+
+            let name = &xs[1];
+            let pattern = self.library.get(name).cloned()
+                .ok_or_else(|| LogError::ConfigError(format!("unknown grok pattern: {}", name)))?;
+            let expanded = self.expand(&pattern, depth + 1)?;
+            match xs.get(2) {
+                Some(field) => {
+                    let kind = match xs.get(3).map(|t| t.as_str()) {
+                        None => FieldKind::Str,
+                        Some("int") => FieldKind::Int,
+                        Some("float") => FieldKind::Float,
+                        Some(t) => return Err(LogError::ConfigError(format!("unknown grok type: {}", t))),
+                    };
+                    let group = format!("f{}", self.fields.len());
+                    out.push_str(&format!("(?P<{}>{})", group, expanded));
+                    self.fields.push(Field { name: field.as_str().to_owned(), group, kind });
+                }
+                None => out.push_str(&format!("(?:{})", expanded)),
+            }
+        }
+        out.push_str(&s[last..]);
+        Ok(out)
+    }
+}
+
+impl LogParser for GrokParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let xs = self.regex.captures(s.trim_end_matches(|c: char| c == '\r' || c == '\n'))
+            .ok_or(LogError::RegexParseError)?;
+
+        let mut m = Map::new();
+        for field in &self.fields {
+            if let Some(x) = xs.name(&field.group) {
+                let v = match field.kind {
+                    FieldKind::Str => Value::from(x.as_str()),
+                    FieldKind::Int => int(x.as_str())?,
+                    FieldKind::Float => float(x.as_str())?,
+                };
+                m.insert(field.name.clone(), v);
+            }
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+#[test]
+fn grok_test() {
+    let parser = GrokParser::new("%{IP:client} %{WORD:method} %{URIPATHPARAM:request} %{NUMBER:bytes:int} %{NUMBER:duration:float}", "").unwrap();
+    let a = parser.parse(b"55.3.244.1 GET /index.html?a=1 15824 0.043").unwrap();
+
+    assert_eq!(a["client"], "55.3.244.1");
+    assert_eq!(a["method"], "GET");
+    assert_eq!(a["request"], "/index.html?a=1");
+    assert_eq!(a["bytes"], 15824);
+    assert_eq!(a["duration"], 0.043);
+
+    assert!(parser.parse(b"55.3.244.1 GET").is_err());
+    assert!(GrokParser::new("%{NOSUCHPATTERN:x}", "").is_err());
+    assert!(GrokParser::new("%{WORD:x:date}", "").is_err());
+    assert!(GrokParser::new("%{LOOP}", "LOOP a%{LOOP}").is_err());
+}
+
+#[test]
+fn grok_patterns_test() {
+    let parser = GrokParser::new("%{COMBINEDAPACHELOG}", "").unwrap();
+    let a = parser.parse(br#"7.248.7.119 - - [14/Dec/2017:22:16:45 +0900] "GET /explore HTTP/1.1" 200 9947 "-" "Mozilla/5.0""#).unwrap();
+    assert_eq!(a["clientip"], "7.248.7.119");
+    assert_eq!(a["timestamp"], "14/Dec/2017:22:16:45 +0900");
+    assert_eq!(a["verb"], "GET");
+    assert_eq!(a["httpversion"], "1.1");
+    assert_eq!(a["response"], 200);
+    assert_eq!(a["bytes"], 9947);
+    assert_eq!(a["agent"], "\"Mozilla/5.0\"");
+    assert!(a.get("rawrequest").is_none());
+
+    let parser = GrokParser::new("%{SYSLOGTIMESTAMP:time} %{QUEUEID:queue_id}: %{GREEDYDATA:message}", "# postfix\nQUEUEID [0-9A-F]{10,11}").unwrap();
+    let a = parser.parse(b"Dec 14 22:16:45 4F9D195432C: removed").unwrap();
+    assert_eq!(a["queue_id"], "4F9D195432C");
+    assert_eq!(a["message"], "removed");
+}
@@ -0,0 +1,264 @@
+use chrono::prelude::*;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{insert_timestamp, offset_from_env, LogParser};
+use super::apache::host_type;
+use super::fields::int;
+use super::request::parse_request_line;
+
+/// Who or what ended the session, the first termination state character.
+fn termination_cause(c: char) -> Option<&'static str> {
+    match c {
+        'C' => Some("client_abort"),
+        'S' => Some("server_abort"),
+        'P' => Some("proxy_abort"),
+        'L' => Some("local"),
+        'R' => Some("resource_exhausted"),
+        'I' => Some("internal_error"),
+        'D' => Some("server_down"),
+        'U' => Some("server_up"),
+        'K' => Some("killed"),
+        'c' => Some("client_timeout"),
+        's' => Some("server_timeout"),
+        _ => None,
+    }
+}
+
+/// What the session was doing when it ended, the second character.
+fn termination_phase(c: char) -> Option<&'static str> {
+    match c {
+        'R' => Some("request"),
+        'Q' => Some("queue"),
+        'C' => Some("connect"),
+        'H' => Some("headers"),
+        'D' => Some("data"),
+        'L' => Some("last_data"),
+        'T' => Some("tarpit"),
+        _ => None,
+    }
+}
+
+/// The persistence cookie the client sent, the third character of HTTP logs.
+fn persistence_cookie(c: char) -> Option<&'static str> {
+    match c {
+        'N' => Some("none"),
+        'I' => Some("invalid"),
+        'D' => Some("down"),
+        'V' => Some("valid"),
+        'E' => Some("expired"),
+        'O' => Some("old"),
+        'U' => Some("unchecked"),
+        _ => None,
+    }
+}
+
+/// What was done with the server's cookie, the fourth character of HTTP logs.
+fn set_cookie(c: char) -> Option<&'static str> {
+    match c {
+        'N' => Some("none"),
+        'I' => Some("inserted"),
+        'U' => Some("updated"),
+        'P' => Some("provided"),
+        'R' => Some("rewritten"),
+        'D' => Some("deleted"),
+        _ => None,
+    }
+}
+
+/// Fields shared by the HTTP and TCP formats, up to the timers.
+const PREFIX: &str = r"^(?:.*?\S+\[\d+\]: )?(\S+):(\d+) \[([^\]]+)\] (\S+) ([^/\s]+)/(\S+) ";
+
+/// Connection counters and queues, which end both formats but for the HTTP extras.
+const COUNTERS: &str = r"(\d+)/(\d+)/(\d+)/(\d+)/(\+?\d+) (\d+)/(\d+)";
+
+/// HAProxy `option httplog` and `option tcplog` lines, with or without the syslog header.
+///
+/// The client address becomes `host`, with `host_type`, and `client_port`; the status
+/// `response`, bytes read `bytes`, and the request line `request` split as in access
+/// logs. Timers are milliseconds, `-1` for phases never reached: `time_request` (Tq,
+/// or TR since 1.7), `time_queue`, `time_connect`, `time_response` (HTTP only) and
+/// `time_total` (Tt, or Ta). The termination state is decoded into
+/// `termination_cause` and `termination_phase`, and for HTTP `persistence_cookie` and
+/// `set_cookie`, null where HAProxy wrote `-`. Captured headers become arrays, the
+/// first braces taken as request headers.
+///
+/// The accept date has no time zone; it is in `HAPROXY_TIMEZONE`, see `offset_from_env`.
+#[derive(Debug)]
+pub struct HaproxyParser {
+    pub offset: FixedOffset,
+    http: Regex,
+    tcp: Regex,
+}
+
+impl Default for HaproxyParser {
+    fn default() -> HaproxyParser {
+        let http = format!(
+            r#"{}(-?\d+)/(-?\d+)/(-?\d+)/(-?\d+)/\+?(-?\d+) (-?\d+) \+?(\d+) (\S+) (\S+) (\S{{4}}) {}(?: \{{([^}}]*)\}})?(?: \{{([^}}]*)\}})? "(.*)"$"#,
+            PREFIX, COUNTERS);
+        let tcp = format!(r"{}(-?\d+)/(-?\d+)/\+?(-?\d+) \+?(\d+) (\S{{2}}) {}$", PREFIX, COUNTERS);
+        HaproxyParser {
+            offset: FixedOffset::east_opt(0).unwrap(),
+            http: Regex::new(&http).unwrap(),
+            tcp: Regex::new(&tcp).unwrap(),
+        }
+    }
+}
+
+impl HaproxyParser {
+    pub fn from_env() -> Result<HaproxyParser, LogError> {
+        Ok(HaproxyParser { offset: offset_from_env("HAPROXY_TIMEZONE")?, ..HaproxyParser::default() })
+    }
+
+    /// The client, accept date, frontend, backend and server common to both formats.
+    fn insert_prefix(&self, m: &mut Map<String, Value>, xs: &regex::Captures) -> Result<(), LogError> {
+        m.insert("host_type".to_owned(), serde_json::to_value(host_type(&xs[1])?)?);
+        m.insert("host".to_owned(), Value::from(&xs[1]));
+        m.insert("client_port".to_owned(), int(&xs[2])?);
+        let t = NaiveDateTime::parse_from_str(&xs[3], "%d/%b/%Y:%H:%M:%S%.f")?;
+        let t = self.offset.from_local_datetime(&t).single().ok_or(LogError::RegexParseError)?;
+        insert_timestamp(m, &t);
+        m.insert("frontend_name".to_owned(), Value::from(&xs[4]));
+        m.insert("backend_name".to_owned(), Value::from(&xs[5]));
+        m.insert("server_name".to_owned(), Value::from(&xs[6]));
+        Ok(())
+    }
+
+    fn parse_http(&self, xs: &regex::Captures) -> Result<Map<String, Value>, LogError> {
+        let mut m = Map::new();
+        self.insert_prefix(&mut m, xs)?;
+        m.insert("time_request".to_owned(), int(&xs[7])?);
+        m.insert("time_queue".to_owned(), int(&xs[8])?);
+        m.insert("time_connect".to_owned(), int(&xs[9])?);
+        m.insert("time_response".to_owned(), int(&xs[10])?);
+        m.insert("time_total".to_owned(), int(&xs[11])?);
+        m.insert("response".to_owned(), int(&xs[12])?);
+        m.insert("bytes".to_owned(), int(&xs[13])?);
+        m.insert("captured_request_cookie".to_owned(), nullable(&xs[14]));
+        m.insert("captured_response_cookie".to_owned(), nullable(&xs[15]));
+        insert_termination_state(&mut m, &xs[16]);
+        insert_counters(&mut m, xs, 17)?;
+        m.insert("captured_request_headers".to_owned(), headers(xs.get(24).map(|x| x.as_str())));
+        m.insert("captured_response_headers".to_owned(), headers(xs.get(25).map(|x| x.as_str())));
+        m.insert("request".to_owned(), Value::from(&xs[26]));
+        if let Value::Object(r) = serde_json::to_value(parse_request_line(&xs[26]))? {
+            m.extend(r);
+        }
+        Ok(m)
+    }
+
+    fn parse_tcp(&self, xs: &regex::Captures) -> Result<Map<String, Value>, LogError> {
+        let mut m = Map::new();
+        self.insert_prefix(&mut m, xs)?;
+        m.insert("time_queue".to_owned(), int(&xs[7])?);
+        m.insert("time_connect".to_owned(), int(&xs[8])?);
+        m.insert("time_total".to_owned(), int(&xs[9])?);
+        m.insert("bytes".to_owned(), int(&xs[10])?);
+        insert_termination_state(&mut m, &xs[11]);
+        insert_counters(&mut m, xs, 12)?;
+        Ok(m)
+    }
+}
+
+impl LogParser for HaproxyParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let s = s.trim_end_matches(|c: char| c == '\r' || c == '\n');
+        let m = if let Some(xs) = self.http.captures(s) {
+            self.parse_http(&xs)?
+        } else if let Some(xs) = self.tcp.captures(s) {
+            self.parse_tcp(&xs)?
+        } else {
+            return Err(LogError::RegexParseError);
+        };
+        Ok(Value::Object(m))
+    }
+}
+
+fn nullable(s: &str) -> Value {
+    if s == "-" { Value::Null } else { Value::from(s) }
+}
+
+fn insert_termination_state(m: &mut Map<String, Value>, s: &str) {
+    let decoders: [fn(char) -> Option<&'static str>; 4] =
+        [termination_cause, termination_phase, persistence_cookie, set_cookie];
+    let names = ["termination_cause", "termination_phase", "persistence_cookie", "set_cookie"];
+    m.insert("termination_state".to_owned(), Value::from(s));
+    for ((&name, decode), c) in names.iter().zip(&decoders).zip(s.chars()) {
+        m.insert(name.to_owned(), decode(c).map(Value::from).unwrap_or(Value::Null));
+    }
+}
+
+/// `actconn/feconn/beconn/srv_conn/retries srv_queue/backend_queue`, from capture `first`.
+fn insert_counters(m: &mut Map<String, Value>, xs: &regex::Captures, first: usize) -> Result<(), LogError> {
+    let names = ["actconn", "feconn", "beconn", "srv_conn", "retries", "srv_queue", "backend_queue"];
+    for (i, &name) in names.iter().enumerate() {
+        m.insert(name.to_owned(), int(xs[first + i].trim_start_matches('+'))?);
+    }
+    Ok(())
+}
+
+/// `{1wt.eu|curl/7.46.0}` holds one value per captured header, possibly empty.
+fn headers(s: Option<&str>) -> Value {
+    match s {
+        Some(s) => Value::Array(s.split('|').map(Value::from).collect()),
+        None => Value::Null,
+    }
+}
+
+#[test]
+fn haproxy_http_test() {
+    let parser = HaproxyParser::default();
+    let data = r#"Feb  6 12:14:14 localhost haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu|} {} "GET /index.html?q=1 HTTP/1.1""#;
+    let a = parser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["host"], "10.0.1.2");
+    assert_eq!(a["host_type"], "ipv4");
+    assert_eq!(a["client_port"], 33317);
+    assert_eq!(a["@timestamp_utc"], "2009-02-06T12:14:14.655+00:00");
+    assert_eq!(a["frontend_name"], "http-in");
+    assert_eq!(a["backend_name"], "static");
+    assert_eq!(a["server_name"], "srv1");
+    assert_eq!(a["time_request"], 10);
+    assert_eq!(a["time_connect"], 30);
+    assert_eq!(a["time_response"], 69);
+    assert_eq!(a["time_total"], 109);
+    assert_eq!(a["response"], 200);
+    assert_eq!(a["bytes"], 2750);
+    assert!(a["captured_request_cookie"].is_null());
+    assert!(a["termination_cause"].is_null());
+    assert_eq!(a["retries"], 0);
+    assert_eq!(a["captured_request_headers"], Value::Array(vec![Value::from("1wt.eu"), Value::from("")]));
+    assert_eq!(a["method"], "GET");
+    assert_eq!(a["path"], "/index.html");
+    assert_eq!(a["query_params"]["q"], "1");
+
+    let a = parser.parse(br#"10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in~ www/<NOSRV> 3/-1/-1/-1/+3 503 +212 - - SQVN 2/2/0/0/+3 0/0 "GET / HTTP/1.1""#).unwrap();
+    assert_eq!(a["time_queue"], -1);
+    assert_eq!(a["time_total"], 3);
+    assert_eq!(a["bytes"], 212);
+    assert_eq!(a["termination_cause"], "server_abort");
+    assert_eq!(a["termination_phase"], "queue");
+    assert_eq!(a["persistence_cookie"], "valid");
+    assert_eq!(a["set_cookie"], "none");
+    assert_eq!(a["retries"], 3);
+    assert!(a["captured_request_headers"].is_null());
+}
+
+#[test]
+fn haproxy_tcp_test() {
+    let parser = HaproxyParser { offset: FixedOffset::east_opt(9 * 3600).unwrap(), ..HaproxyParser::default() };
+    let a = parser.parse(b"Feb  6 12:12:56 localhost haproxy[14387]: 10.0.1.2:33313 [06/Feb/2009:12:12:51.443] fnt bck/srv1 0/0/5007 212 cD 0/0/0/0/3 0/0").unwrap();
+
+    assert_eq!(a["@timestamp_utc"], "2009-02-06T03:12:51.443+00:00");
+    assert_eq!(a["time_queue"], 0);
+    assert_eq!(a["time_total"], 5007);
+    assert_eq!(a["bytes"], 212);
+    assert_eq!(a["termination_cause"], "client_timeout");
+    assert_eq!(a["termination_phase"], "data");
+    assert!(a.get("persistence_cookie").is_none());
+    assert_eq!(a["retries"], 3);
+
+    assert!(parser.parse(b"Feb  6 12:12:56 localhost haproxy[14387]: Proxy fnt started.").is_err());
+}
@@ -0,0 +1,171 @@
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{from_epoch_millis, from_epoch_secs, insert_timestamp, LogParser};
+
+/// How the timestamp field of a JSON log is written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimestampFormat {
+    Rfc3339,
+    /// Seconds since the epoch, possibly fractional, as a number or a string.
+    Epoch,
+    EpochMillis,
+    /// A chrono format string. Times without an offset are taken as UTC.
+    Custom(String),
+}
+
+impl TimestampFormat {
+    fn new(s: &str) -> TimestampFormat {
+        match s {
+            "rfc3339" => TimestampFormat::Rfc3339,
+            "epoch" => TimestampFormat::Epoch,
+            "epoch_millis" => TimestampFormat::EpochMillis,
+            _ => TimestampFormat::Custom(s.to_owned()),
+        }
+    }
+
+    fn parse(&self, v: &Value) -> Result<DateTime<FixedOffset>, LogError> {
+        let s = match *v {
+            Value::String(ref s) => s.clone(),
+            Value::Number(ref n) => n.to_string(),
+            _ => return Err(LogError::RegexParseError),
+        };
+        let utc = |t: DateTime<Utc>| t.with_timezone(&FixedOffset::east_opt(0).unwrap());
+        match *self {
+            TimestampFormat::Rfc3339 => Ok(DateTime::parse_from_rfc3339(&s)?),
+            TimestampFormat::Epoch => Ok(utc(from_epoch_secs(s.parse::<f64>()?)?)),
+            TimestampFormat::EpochMillis => Ok(utc(from_epoch_millis(s.parse::<f64>()? as i64)?)),
+            TimestampFormat::Custom(ref format) => match DateTime::parse_from_str(&s, format) {
+                Ok(time) => Ok(time),
+                Err(_) => Ok(utc(Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(&s, format)?))),
+            },
+        }
+    }
+}
+
+/// Lines that are already JSON objects.
+///
+/// Each line is validated and re-serialised. With `JSON_FLATTEN=true` nested objects
+/// become dotted keys, `{"a": {"b": 1}}` giving `{"a.b": 1}`. When `JSON_TIMESTAMP_FIELD`
+/// names a field, by dotted path, it also gives the `@timestamp` pair, read according to
+/// `JSON_TIMESTAMP_FORMAT`: `rfc3339` (the default), `epoch`, `epoch_millis` or a chrono
+/// format string. Lines without the field are passed through as they are.
+#[derive(Debug)]
+pub struct JsonParser {
+    pub flatten: bool,
+    pub timestamp_field: Option<String>,
+    pub timestamp_format: TimestampFormat,
+}
+
+impl Default for JsonParser {
+    fn default() -> JsonParser {
+        JsonParser {
+            flatten: false,
+            timestamp_field: None,
+            timestamp_format: TimestampFormat::Rfc3339,
+        }
+    }
+}
+
+impl JsonParser {
+    pub fn from_env() -> Result<JsonParser, LogError> {
+        let flatten = match std::env::var("JSON_FLATTEN") {
+            Ok(ref s) if s == "true" => true,
+            Ok(ref s) if s == "false" => false,
+            Ok(s) => return Err(LogError::ConfigError(format!("unknown JSON_FLATTEN: {}", s))),
+            Err(_) => false,
+        };
+        Ok(JsonParser {
+            flatten,
+            timestamp_field: std::env::var("JSON_TIMESTAMP_FIELD").ok(),
+            timestamp_format: std::env::var("JSON_TIMESTAMP_FORMAT")
+                .map(|s| TimestampFormat::new(&s))
+                .unwrap_or(TimestampFormat::Rfc3339),
+        })
+    }
+}
+
+impl LogParser for JsonParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let mut m = serde_json::from_slice::<Map<String, Value>>(data)?;
+
+        let time = match self.timestamp_field {
+            Some(ref path) => match lookup(&m, path) {
+                Some(v) => Some(self.timestamp_format.parse(v)?),
+                None => None,
+            },
+            None => None,
+        };
+
+        if self.flatten {
+            let mut flat = Map::new();
+            flatten_into(&mut flat, "", m);
+            m = flat;
+        }
+        if let Some(time) = time {
+            insert_timestamp(&mut m, &time);
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+/// The value at a dotted `path`, either nested or already a dotted key.
+fn lookup<'a>(m: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    if let Some(v) = m.get(path) {
+        return Some(v);
+    }
+    let mut parts = path.split('.');
+    let mut v = m.get(parts.next()?)?;
+    for part in parts {
+        v = v.get(part)?;
+    }
+    Some(v)
+}
+
+fn flatten_into(out: &mut Map<String, Value>, prefix: &str, m: Map<String, Value>) {
+    for (k, v) in m {
+        let key = if prefix.is_empty() { k } else { format!("{}.{}", prefix, k) };
+        match v {
+            Value::Object(inner) => flatten_into(out, &key, inner),
+            v => {
+                out.insert(key, v);
+            }
+        }
+    }
+}
+
+#[test]
+fn json_test() {
+    let parser = JsonParser::default();
+    let a = parser.parse(br#"{"level":"info","http":{"status":200}}"#).unwrap();
+    assert_eq!(a["http"]["status"], 200);
+    assert!(a.get("@timestamp").is_none());
+
+    assert!(parser.parse(b"[1, 2]").is_err());
+    assert!(parser.parse(b"{\"level\":").is_err());
+}
+
+#[test]
+fn json_flatten_timestamp_test() {
+    let parser = JsonParser {
+        flatten: true,
+        timestamp_field: Some("meta.time".to_owned()),
+        timestamp_format: TimestampFormat::Rfc3339,
+    };
+    let a = parser.parse(br#"{"meta":{"time":"2017-12-14T22:16:45+09:00","tags":["a"]},"http":{"req":{"path":"/"}}}"#).unwrap();
+    assert_eq!(a["meta.time"], "2017-12-14T22:16:45+09:00");
+    assert_eq!(a["meta.tags"][0], "a");
+    assert_eq!(a["http.req.path"], "/");
+    assert_eq!(a["@timestamp"], "2017-12-14T22:16:45+09:00");
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+
+    let parser = JsonParser { timestamp_format: TimestampFormat::new("epoch"), ..parser };
+    let a = parser.parse(br#"{"meta":{"time":1513257405.5}}"#).unwrap();
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45.500+00:00");
+
+    let parser = JsonParser { timestamp_format: TimestampFormat::new("%Y-%m-%d %H:%M:%S"), ..parser };
+    let a = parser.parse(br#"{"meta":{"time":"2017-12-14 13:16:45"}}"#).unwrap();
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+    assert!(parser.parse(br#"{"meta":{"time":"yesterday"}}"#).is_err());
+}
@@ -0,0 +1,218 @@
+use chrono::DateTime;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{from_epoch_secs, insert_timestamp, LogParser};
+
+/// `key=value` lines in logfmt, as Go services commonly write them:
+/// `level=info msg="started server" dur=12ms`.
+///
+/// Quoted values may contain spaces and the escapes `\"`, `\\`, `\n`, `\r` and `\t`,
+/// and a key without `=` is `true`. With `LOGFMT_INFER_TYPES=true` unquoted values
+/// that look like integers, floats, booleans or Go durations become numbers and
+/// booleans, durations in float seconds. `LOGFMT_TIME_KEY` names the key, RFC3339 or
+/// epoch seconds, that is moved into the `@timestamp` pair.
+#[derive(Debug, Default)]
+pub struct LogfmtParser {
+    pub infer_types: bool,
+    pub time_key: Option<String>,
+}
+
+impl LogfmtParser {
+    pub fn from_env() -> Result<LogfmtParser, LogError> {
+        let infer_types = match std::env::var("LOGFMT_INFER_TYPES") {
+            Ok(ref s) if s == "true" => true,
+            Ok(ref s) if s == "false" => false,
+            Ok(s) => return Err(LogError::ConfigError(format!("unknown LOGFMT_INFER_TYPES: {}", s))),
+            Err(_) => false,
+        };
+        Ok(LogfmtParser { infer_types, time_key: std::env::var("LOGFMT_TIME_KEY").ok() })
+    }
+}
+
+impl LogParser for LogfmtParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+
+        let mut m = Map::new();
+        for (key, value) in split_pairs(&s)? {
+            let v = match value {
+                Token::Bare(x) if self.infer_types => infer(x),
+                Token::Bare(x) => Value::from(x),
+                Token::Quoted(x) => Value::from(x),
+                Token::Flag => Value::Bool(true),
+            };
+            m.insert(key.to_owned(), v);
+        }
+
+        if let Some(ref key) = self.time_key {
+            if let Some(v) = m.remove(key) {
+                match v {
+                    Value::String(ref s) => match s.parse::<f64>() {
+                        Ok(secs) => insert_timestamp(&mut m, &from_epoch_secs(secs)?),
+                        Err(_) => insert_timestamp(&mut m, &DateTime::parse_from_rfc3339(s)?),
+                    },
+                    Value::Number(ref n) => {
+                        let secs = n.as_f64().ok_or(LogError::RegexParseError)?;
+                        insert_timestamp(&mut m, &from_epoch_secs(secs)?);
+                    }
+                    _ => return Err(LogError::RegexParseError),
+                }
+            }
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Bare(&'a str),
+    Quoted(String),
+    /// A key with no `=`.
+    Flag,
+}
+
+/// Splits a line into keys and their values. An unterminated quote is an error.
+fn split_pairs(s: &str) -> Result<Vec<(&str, Token)>, LogError> {
+    let mut pairs = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let key_end = rest.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        if key.is_empty() {
+            return Err(LogError::RegexParseError);
+        }
+        rest = &rest[key_end..];
+
+        let value = match rest.strip_prefix('=') {
+            None => Token::Flag,
+            Some(r) => match r.strip_prefix('"') {
+                Some(quoted) => {
+                    let (value, len) = unquote(quoted).ok_or(LogError::RegexParseError)?;
+                    rest = &quoted[len..];
+                    Token::Quoted(value)
+                }
+                None => {
+                    let end = r.find(char::is_whitespace).unwrap_or(r.len());
+                    rest = &r[end..];
+                    Token::Bare(&r[..end])
+                }
+            },
+        };
+        pairs.push((key, value));
+        rest = rest.trim_start();
+    }
+    Ok(pairs)
+}
+
+/// Reads a quoted value up to and including its closing quote, returning the
+/// unescaped value and the length consumed.
+fn unquote(s: &str) -> Option<(String, usize)> {
+    let mut out = String::new();
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            match c {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                '"' | '\\' => out.push(c),
+                _ => {
+                    out.push('\\');
+                    out.push(c);
+                }
+            }
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            return Some((out, i + 1));
+        } else {
+            out.push(c);
+        }
+    }
+    None
+}
+
+fn infer(s: &str) -> Value {
+    match s {
+        "true" => return Value::Bool(true),
+        "false" => return Value::Bool(false),
+        _ => (),
+    }
+    let numeric = !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b));
+    if numeric {
+        if let Ok(n) = s.parse::<i64>() {
+            return Value::from(n);
+        }
+        if let Ok(x) = s.parse::<f64>() {
+            return Value::from(x);
+        }
+    }
+    parse_duration(s).map(Value::from).unwrap_or_else(|| Value::from(s))
+}
+
+/// Go's `time.Duration` notation, e.g. `1h2m3.5s` or `-250us`, in seconds.
+fn parse_duration(s: &str) -> Option<f64> {
+    let (sign, mut rest) = match s.strip_prefix('-') {
+        Some(r) => (-1.0, r),
+        None => (1.0, s.strip_prefix('+').unwrap_or(s)),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut secs = 0.0;
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        let n = rest[..number_end].parse::<f64>().ok()?;
+        rest = &rest[number_end..];
+        let unit_end = rest.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(rest.len());
+        let scale = match &rest[..unit_end] {
+            "ns" => 1e-9,
+            "us" | "µs" | "μs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return None,
+        };
+        secs += n * scale;
+        rest = &rest[unit_end..];
+    }
+    Some(sign * secs)
+}
+
+#[test]
+fn logfmt_test() {
+    let parser = LogfmtParser::default();
+    let a = parser.parse(br#"level=info msg="started \"api\" server" port=8080 debug path=/a=b empty="""#).unwrap();
+
+    assert_eq!(a["level"], "info");
+    assert_eq!(a["msg"], "started \"api\" server");
+    assert_eq!(a["port"], "8080");
+    assert_eq!(a["debug"], true);
+    assert_eq!(a["path"], "/a=b");
+    assert_eq!(a["empty"], "");
+
+    assert!(parser.parse(br#"msg="unterminated"#).is_err());
+}
+
+#[test]
+fn logfmt_infer_types_test() {
+    let parser = LogfmtParser { infer_types: true, time_key: Some("ts".to_owned()) };
+    let a = parser.parse(br#"ts=2017-12-14T22:16:45+09:00 port=8080 ratio=0.5 ok=false dur=1m30.5s wait=250us code="404" name=NaN"#).unwrap();
+
+    assert!(a.get("ts").is_none());
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+    assert_eq!(a["port"], 8080);
+    assert_eq!(a["ratio"], 0.5);
+    assert_eq!(a["ok"], false);
+    assert_eq!(a["dur"], 90.5);
+    assert_eq!(a["wait"], 0.00025);
+    assert_eq!(a["code"], "404");
+    assert_eq!(a["name"], "NaN");
+
+    let a = parser.parse(b"ts=1513257405 msg=hi").unwrap();
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+}
@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+
+use error::LogError;
+
+mod alb;
+mod apache;
+mod cef;
+mod cloudfront;
+mod csv;
+mod elb;
+mod error_log;
+mod fields;
+mod grok;
+mod haproxy;
+mod json;
+mod logfmt;
+mod nginx;
+mod request;
+mod s3;
+mod syslog;
+mod vpc_flow;
+
+pub use self::alb::AlbParser;
+pub use self::apache::ApacheParser;
+pub use self::cef::{CefParser, LeefParser};
+pub use self::cloudfront::CloudFrontParser;
+pub use self::csv::CsvParser;
+pub use self::elb::ElbParser;
+pub use self::error_log::ErrorLogParser;
+pub use self::grok::GrokParser;
+pub use self::haproxy::HaproxyParser;
+pub use self::json::JsonParser;
+pub use self::logfmt::LogfmtParser;
+pub use self::nginx::NginxParser;
+pub use self::s3::S3Parser;
+pub use self::syslog::SyslogParser;
+pub use self::vpc_flow::VpcFlowParser;
+
+/// What is known about the Firehose record a line came from.
+#[derive(Debug, Default, Clone)]
+pub struct RecordContext {
+    pub arrival_time: Option<DateTime<Utc>>,
+    /// Column names announced by a header directive earlier in the same record.
+    pub header_fields: RefCell<Option<Vec<String>>>,
+}
+
+/// Turns the raw bytes of a single log line into a JSON document, or `Value::Null`
+/// for lines that carry no entry, such as header directives.
+pub trait LogParser: Send + Sync {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError>;
+
+    /// Parsers that need more than the line itself, such as syslog for the year its
+    /// timestamps leave out, override this.
+    fn parse_with_context(&self, data: &[u8], _ctx: &RecordContext) -> Result<Value, LogError> {
+        self.parse(data)
+    }
+}
+
+/// Builds the parser named by the `LOG_FORMAT` environment variable.
+pub fn from_env() -> Result<Box<dyn LogParser>, LogError> {
+    let name = std::env::var("LOG_FORMAT").unwrap_or_else(|_| "apache".to_owned());
+    from_name(&name).map_err(|e| LogError::ConfigError(format!("LOG_FORMAT={}: {}", name, e)))
+}
+
+/// Builds the parser called `name`; parser specific options are read from the environment.
+pub fn from_name(name: &str) -> Result<Box<dyn LogParser>, LogError> {
+    match name {
+        "apache" => Ok(Box::new(ApacheParser::from_env()?)),
+        "nginx" => Ok(Box::new(NginxParser::from_env()?)),
+        "alb" => Ok(Box::new(AlbParser)),
+        "elb" => Ok(Box::new(ElbParser)),
+        "cloudfront" => Ok(Box::new(CloudFrontParser::from_env())),
+        "s3" => Ok(Box::new(S3Parser)),
+        "vpc_flow" => Ok(Box::new(VpcFlowParser::from_env()?)),
+        "syslog" => Ok(Box::new(SyslogParser::default())),
+        "cef" => Ok(Box::new(CefParser)),
+        "leef" => Ok(Box::new(LeefParser)),
+        "json" => Ok(Box::new(JsonParser::from_env()?)),
+        "logfmt" => Ok(Box::new(LogfmtParser::from_env()?)),
+        "csv" => Ok(Box::new(CsvParser::from_env()?)),
+        "grok" => Ok(Box::new(GrokParser::from_env()?)),
+        "error_log" => Ok(Box::new(ErrorLogParser::from_env()?)),
+        "haproxy" => Ok(Box::new(HaproxyParser::from_env()?)),
+        _ => Err(LogError::ConfigError(format!("unknown LOG_FORMAT: {}", name))),
+    }
+}
+
+/// Adds the `@timestamp` and `@timestamp_utc` pair of the Apache access log record.
+pub fn insert_timestamp<Tz: TimeZone>(m: &mut Map<String, Value>, time: &DateTime<Tz>)
+    where Tz::Offset: std::fmt::Display
+{
+    m.insert("@timestamp".to_owned(), Value::from(time.to_rfc3339()));
+    m.insert("@timestamp_utc".to_owned(), Value::from(time.with_timezone(&Utc).to_rfc3339()));
+}
+
+pub fn from_epoch(secs: i64, nanos: u32) -> Result<DateTime<Utc>, LogError> {
+    Utc.timestamp_opt(secs, nanos).single()
+        .ok_or_else(|| LogError::TimestampRangeError(secs))
+}
+
+pub fn from_epoch_millis(ms: i64) -> Result<DateTime<Utc>, LogError> {
+    from_epoch(ms.div_euclid(1000), (ms.rem_euclid(1000) * 1_000_000) as u32)
+}
+
+/// Seconds since the epoch with a fraction, such as nginx's `$msec`. The fraction is
+/// rounded to the nanosecond but kept below a whole second, which chrono would take
+/// for a leap second.
+pub fn from_epoch_secs(secs: f64) -> Result<DateTime<Utc>, LogError> {
+    if !secs.is_finite() {
+        return Err(LogError::TimestampRangeError(secs as i64));
+    }
+    let whole = secs.floor();
+    let nanos = ((secs - whole) * 1_000_000_000.0).round().min(999_999_999.0) as u32;
+    from_epoch(whole as i64, nanos)
+}
+
+/// A fixed UTC offset such as `+09:00` or `-0500`, or `Z` or `UTC`, for formats
+/// that write local times without one.
+pub fn parse_offset(s: &str) -> Option<FixedOffset> {
+    if s == "Z" || s == "UTC" {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, digits) = match (s.strip_prefix('+'), s.strip_prefix('-')) {
+        (Some(d), _) => (1, d.replace(':', "")),
+        (_, Some(d)) => (-1, d.replace(':', "")),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let secs = digits[..2].parse::<i32>().ok()? * 3600 + digits[2..].parse::<i32>().ok()? * 60;
+    FixedOffset::east_opt(sign * secs)
+}
+
+/// The offset in the environment variable `name`, as `parse_offset` reads it, or UTC
+/// when it is unset.
+pub fn offset_from_env(name: &str) -> Result<FixedOffset, LogError> {
+    match std::env::var(name) {
+        Ok(s) => parse_offset(&s).ok_or_else(|| LogError::ConfigError(format!("invalid {}: {}", name, s))),
+        Err(_) => Ok(FixedOffset::east_opt(0).unwrap()),
+    }
+}
+
+#[test]
+fn from_name_test() {
+    assert!(from_name("apache").is_ok());
+    assert!(from_name("nginx").is_ok());
+    assert!(from_name("alb").is_ok());
+    assert!(from_name("elb").is_ok());
+    assert!(from_name("cloudfront").is_ok());
+    assert!(from_name("s3").is_ok());
+    assert!(from_name("vpc_flow").is_ok());
+    assert!(from_name("syslog").is_ok());
+    assert!(from_name("cef").is_ok());
+    assert!(from_name("leef").is_ok());
+    assert!(from_name("json").is_ok());
+    assert!(from_name("logfmt").is_ok());
+    assert!(from_name("error_log").is_ok());
+    assert!(from_name("haproxy").is_ok());
+    // CSV_COLUMNS and GROK_PATTERN have no default.
+    assert!(from_name("csv").is_err());
+    assert!(from_name("grok").is_err());
+    assert!(from_name("unknown").is_err());
+}
+
+#[test]
+fn parse_offset_test() {
+    assert_eq!(parse_offset("+09:00"), FixedOffset::east_opt(9 * 3600));
+    assert_eq!(parse_offset("-0530"), FixedOffset::west_opt(5 * 3600 + 30 * 60));
+    assert_eq!(parse_offset("UTC"), FixedOffset::east_opt(0));
+    assert!(parse_offset("JST").is_none());
+    assert!(parse_offset("+9").is_none());
+}
+
+#[test]
+fn from_epoch_secs_test() {
+    assert_eq!(from_epoch_secs(1513257405.5).unwrap().to_rfc3339(), "2017-12-14T13:16:45.500+00:00");
+    assert_eq!(from_epoch_secs(-1.5).unwrap().to_rfc3339(), "1969-12-31T23:59:58.500+00:00");
+    assert_eq!(from_epoch_secs(1.9999999999).unwrap().timestamp_subsec_nanos(), 999_999_999);
+    assert!(from_epoch_secs(f64::NAN).is_err());
+}
@@ -0,0 +1,194 @@
+use chrono::DateTime;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{from_epoch_secs, insert_timestamp, LogParser};
+use super::apache::{host_type, parse_clf_time};
+use super::request::parse_request_line;
+
+/// nginx's predefined `combined` format.
+pub const COMBINED: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#;
+
+/// How a captured variable is turned into JSON.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldKind {
+    Str,
+    Int,
+    /// Seconds with millisecond resolution. Upstream timings hold one value per
+    /// upstream tried, e.g. `0.001, 0.020`, and become an array in that case.
+    Timing,
+    Host,
+    Request,
+    TimeLocal,
+    TimeIso8601,
+    Msec,
+}
+
+#[derive(Debug)]
+struct Field {
+    name: String,
+    kind: FieldKind,
+}
+
+impl Field {
+    /// Variables shared with Apache are renamed to its field names, such as `host`,
+    /// `authuser`, `response`, `bytes`, `referer` and `user_agent`.
+    fn new(variable: &str) -> Field {
+        let (name, kind) = match variable {
+            "remote_addr" => ("host", FieldKind::Host),
+            "remote_user" => ("authuser", FieldKind::Str),
+            "time_local" => ("@timestamp", FieldKind::TimeLocal),
+            "time_iso8601" => ("@timestamp", FieldKind::TimeIso8601),
+            "msec" => ("@timestamp", FieldKind::Msec),
+            "request" => ("request", FieldKind::Request),
+            "status" => ("response", FieldKind::Int),
+            "body_bytes_sent" => ("bytes", FieldKind::Int),
+            "http_referer" => ("referer", FieldKind::Str),
+            "http_user_agent" => ("user_agent", FieldKind::Str),
+            "bytes_sent" | "request_length" | "content_length" | "connection"
+            | "connection_requests" | "server_port" | "remote_port" => (variable, FieldKind::Int),
+            "request_time" | "upstream_response_time" | "upstream_connect_time"
+            | "upstream_header_time" | "upstream_queue_time" => (variable, FieldKind::Timing),
+            _ => (variable, FieldKind::Str),
+        };
+        Field { name: name.to_owned(), kind }
+    }
+
+    fn insert(&self, m: &mut Map<String, Value>, s: &str) -> Result<(), LogError> {
+        match self.kind {
+            FieldKind::Str => {
+                m.insert(self.name.clone(), Value::from(s));
+            }
+            FieldKind::Int => {
+                let v = if s == "-" { Value::Null } else { Value::from(s.parse::<u64>()?) };
+                m.insert(self.name.clone(), v);
+            }
+            FieldKind::Timing => {
+                let mut xs = s.split(|c: char| c == ',' || c == ':')
+                    .map(|x| match x.trim() {
+                        "-" => Ok(Value::Null),
+                        x => x.parse::<f64>().map(Value::from),
+                    })
+                    .collect::<Result<Vec<Value>, _>>()?;
+                let v = if xs.len() == 1 { xs.remove(0) } else { Value::Array(xs) };
+                m.insert(self.name.clone(), v);
+            }
+            FieldKind::Host => {
+                m.insert("host_type".to_owned(), serde_json::to_value(host_type(s)?)?);
+                m.insert(self.name.clone(), Value::from(s));
+            }
+            FieldKind::Request => {
+                m.insert(self.name.clone(), Value::from(s));
+                if let Value::Object(r) = serde_json::to_value(parse_request_line(s))? {
+                    m.extend(r);
+                }
+            }
+            FieldKind::TimeLocal => insert_timestamp(m, &parse_clf_time(s)?),
+            FieldKind::TimeIso8601 => insert_timestamp(m, &DateTime::parse_from_rfc3339(s)?),
+            FieldKind::Msec => insert_timestamp(m, &from_epoch_secs(s.parse::<f64>()?)?),
+        }
+        Ok(())
+    }
+}
+
+/// nginx access log in a `log_format` given by `NGINX_LOG_FORMAT`, `combined` by default.
+///
+/// Each `$variable` becomes a capture matching up to the literal text that follows it.
+#[derive(Debug)]
+pub struct NginxParser {
+    regex: Regex,
+    fields: Vec<Field>,
+}
+
+impl NginxParser {
+    pub fn from_env() -> Result<NginxParser, LogError> {
+        match std::env::var("NGINX_LOG_FORMAT") {
+            Ok(format) => NginxParser::new(&format),
+            Err(_) => NginxParser::new(COMBINED),
+        }
+    }
+
+    pub fn new(format: &str) -> Result<NginxParser, LogError> {
+        let mut pattern = "^".to_owned();
+        let mut fields = Vec::new();
+        let mut rest = format;
+        while let Some(i) = rest.find('$') {
+            pattern.push_str(&regex::escape(&rest[..i]));
+            let (variable, next) = split_variable(&rest[i + 1..]);
+            if variable.is_empty() {
+                return Err(LogError::ConfigError(format!("invalid nginx log_format: {}", format)));
+            }
+            pattern.push_str("(.*?)");
+            fields.push(Field::new(variable));
+            rest = next;
+        }
+        pattern.push_str(&regex::escape(rest));
+        pattern.push_str(r"\s*$");
+
+        let regex = Regex::new(&pattern)
+            .map_err(|e| LogError::ConfigError(format!("invalid nginx log_format: {}", e)))?;
+        Ok(NginxParser { regex, fields })
+    }
+}
+
+/// Splits `remote_addr - ...` or `{remote_addr}- ...` into the variable name and the rest.
+fn split_variable(s: &str) -> (&str, &str) {
+    if s.starts_with('{') {
+        match s.find('}') {
+            Some(end) => (&s[1..end], &s[end + 1..]),
+            None => ("", s),
+        }
+    } else {
+        let end = s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(s.len());
+        (&s[..end], &s[end..])
+    }
+}
+
+impl LogParser for NginxParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let xs = self.regex.captures(&s).ok_or(LogError::RegexParseError)?;
+
+        let mut m = Map::new();
+        for (field, x) in self.fields.iter().zip(xs.iter().skip(1)) {
+            if let Some(x) = x {
+                field.insert(&mut m, x.as_str())?;
+            }
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+#[test]
+fn combined_test() {
+    let parser = NginxParser::new(COMBINED).unwrap();
+    let data = r#"7.248.7.119 - - [14/Dec/2017:22:16:45 +0900] "GET /explore?q=1 HTTP/1.1" 200 9947 "-" "Mozilla/5.0 (Windows NT 6.2; WOW64; rv:8.5) Gecko/20100101 Firefox/8.5.1""#;
+    let a = parser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["host"], "7.248.7.119");
+    assert_eq!(a["host_type"], "ipv4");
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+    assert_eq!(a["method"], "GET");
+    assert_eq!(a["query_params"]["q"], "1");
+    assert_eq!(a["response"], 200);
+    assert_eq!(a["bytes"], 9947);
+    assert_eq!(a["user_agent"], "Mozilla/5.0 (Windows NT 6.2; WOW64; rv:8.5) Gecko/20100101 Firefox/8.5.1");
+}
+
+#[test]
+fn custom_format_test() {
+    let parser = NginxParser::new(r#"$remote_addr [$time_iso8601] "$request" $status ${body_bytes_sent} rt=$request_time uct="$upstream_connect_time" urt="$upstream_response_time" ua=$upstream_addr"#).unwrap();
+    let data = r#"2001:db8::1 [2017-12-14T22:16:45+09:00] "POST /api HTTP/2.0" 502 - rt=0.125 uct="0.001, -" urt="0.100, 0.020" ua=10.0.0.1:80, 10.0.0.2:80"#;
+    let a = parser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["host_type"], "ipv6");
+    assert_eq!(a["@timestamp_utc"], "2017-12-14T13:16:45+00:00");
+    assert!(a["bytes"].is_null());
+    assert_eq!(a["request_time"], 0.125);
+    assert_eq!(a["upstream_connect_time"], Value::Array(vec![Value::from(0.001), Value::Null]));
+    assert_eq!(a["upstream_response_time"], Value::Array(vec![Value::from(0.1), Value::from(0.02)]));
+    assert_eq!(a["upstream_addr"], "10.0.0.1:80, 10.0.0.2:80");
+
+    assert!(NginxParser::new("$remote_addr ${").is_err());
+}
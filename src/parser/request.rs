@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+
+/// Components of an HTTP request line such as `GET /explore?q=rust HTTP/1.1`.
+///
+/// Every field is `None` when the line is not a well-formed request (`-`, or the
+/// binary junk scanners send), so a bad request line never fails the whole record.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct RequestLine {
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub query_string: Option<String>,
+    pub protocol: Option<String>,
+    pub query_params: BTreeMap<String, String>,
+}
+
+pub fn parse_request_line(s: &str) -> RequestLine {
+    let mut xs = s.split(' ');
+    let (method, target, protocol) = match (xs.next(), xs.next(), xs.next(), xs.next()) {
+        (Some(m), Some(t), p, None) => (m, t, p),
+        _ => return RequestLine::default(),
+    };
+    let valid_method = !method.is_empty() && method.chars().all(|c| c.is_ascii_uppercase());
+    let valid_protocol = protocol.map_or(true, |p| p.starts_with("HTTP/"));
+    let valid_target = !target.is_empty() && !target.chars().any(|c| c.is_control());
+    if !(valid_method && valid_protocol && valid_target) {
+        return RequestLine::default();
+    }
+
+    let (path, query_string) = match target.find('?') {
+        Some(i) => (&target[..i], Some(&target[i + 1..])),
+        None => (target, None),
+    };
+    let query_params = query_string.map(parse_query_string).unwrap_or_default();
+
+    RequestLine {
+        method: Some(method.to_owned()),
+        path: Some(path.to_owned()),
+        query_string: query_string.map(|q| q.to_owned()),
+        protocol: protocol.map(|p| p.to_owned()),
+        query_params,
+    }
+}
+
+/// Decodes `a=1&b=x%20y` into a map. Later duplicates of a key win.
+pub fn parse_query_string(s: &str) -> BTreeMap<String, String> {
+    s.split('&')
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| match kv.find('=') {
+            Some(i) => (url_decode(&kv[..i]), url_decode(&kv[i + 1..])),
+            None => (url_decode(kv), String::new()),
+        })
+        .collect()
+}
+
+/// Percent-decodes `s`, treating `+` as a space. Malformed escapes are kept verbatim
+/// and invalid UTF-8 is replaced rather than rejected.
+pub fn url_decode(s: &str) -> String {
+    decode(s, true)
+}
+
+/// Like `url_decode`, but leaves `+` alone.
+pub fn percent_decode(s: &str) -> String {
+    decode(s, false)
+}
+
+fn decode(s: &str, plus_as_space: bool) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            hex_value(bytes[i + 1]).and_then(|h| hex_value(bytes[i + 2]).map(|l| h * 16 + l))
+        } else {
+            None
+        };
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(if plus_as_space && bytes[i] == b'+' { b' ' } else { bytes[i] });
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[test]
+fn parse_request_line_test() {
+    let r = parse_request_line("GET /search?q=rust+lang&page=2&q2=%E3%81%82 HTTP/1.1");
+    assert_eq!(r.method, Some("GET".to_owned()));
+    assert_eq!(r.path, Some("/search".to_owned()));
+    assert_eq!(r.query_string, Some("q=rust+lang&page=2&q2=%E3%81%82".to_owned()));
+    assert_eq!(r.protocol, Some("HTTP/1.1".to_owned()));
+    assert_eq!(r.query_params["q"], "rust lang");
+    assert_eq!(r.query_params["q2"], "あ");
+
+    let r = parse_request_line("GET /explore");
+    assert_eq!(r.path, Some("/explore".to_owned()));
+    assert_eq!(r.protocol, None);
+
+    assert_eq!(parse_request_line("-"), RequestLine::default());
+    assert_eq!(parse_request_line("\\x16\\x03\\x01\\x00"), RequestLine::default());
+    assert_eq!(url_decode("100%"), "100%");
+    assert_eq!(percent_decode("a+b%20c"), "a+b c");
+}
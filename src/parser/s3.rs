@@ -0,0 +1,125 @@
+use chrono::prelude::*;
+use serde_json::Value;
+
+use error::LogError;
+use super::LogParser;
+use super::apache::parse_clf_time;
+use super::fields::split_fields;
+use super::request::{parse_request_line, RequestLine};
+
+/// Fields every S3 server access log entry has, up to `user_agent`.
+const REQUIRED_FIELDS: usize = 17;
+
+#[derive(Serialize, Debug)]
+struct S3AccessLog {
+    bucket_owner: String,
+    bucket: String,
+    #[serde(rename = "@timestamp")]
+    timestamp: String,
+    #[serde(rename = "@timestamp_utc")]
+    timestamp_utc: String,
+    remote_ip: String,
+    requester: String,
+    request_id: String,
+    operation: String,
+    key: String,
+    request_uri: String,
+    #[serde(flatten)]
+    request_line: RequestLine,
+    http_status: Option<u32>,
+    error_code: String,
+    bytes_sent: Option<u64>,
+    object_size: Option<u64>,
+    total_time: Option<u64>,
+    turn_around_time: Option<u64>,
+    referer: String,
+    user_agent: String,
+    version_id: Option<String>,
+    host_id: Option<String>,
+    signature_version: Option<String>,
+    cipher_suite: Option<String>,
+    authentication_type: Option<String>,
+    host_header: Option<String>,
+    tls_version: Option<String>,
+    access_point_arn: Option<String>,
+    acl_required: Option<String>,
+}
+
+/// Amazon S3 server access log. Times are in milliseconds; numbers S3 logs as `-`
+/// and fields older entries lack are null.
+#[derive(Debug, Default)]
+pub struct S3Parser;
+
+fn number<T: std::str::FromStr>(s: &str) -> Result<Option<T>, T::Err> {
+    match s {
+        "-" => Ok(None),
+        _ => s.parse().map(Some),
+    }
+}
+
+impl LogParser for S3Parser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let xs = split_fields(&s);
+        if xs.len() < REQUIRED_FIELDS {
+            return Err(LogError::RegexParseError);
+        }
+
+        let time = parse_clf_time(xs[2])?;
+        let optional = |i: usize| xs.get(i).map(|x| (*x).to_owned());
+        let log = S3AccessLog {
+            bucket_owner: xs[0].to_owned(),
+            bucket: xs[1].to_owned(),
+            timestamp: time.to_rfc3339(),
+            timestamp_utc: time.with_timezone(&Utc).to_rfc3339(),
+            remote_ip: xs[3].to_owned(),
+            requester: xs[4].to_owned(),
+            request_id: xs[5].to_owned(),
+            operation: xs[6].to_owned(),
+            key: xs[7].to_owned(),
+            request_uri: xs[8].to_owned(),
+            request_line: parse_request_line(xs[8]),
+            http_status: number(xs[9])?,
+            error_code: xs[10].to_owned(),
+            bytes_sent: number(xs[11])?,
+            object_size: number(xs[12])?,
+            total_time: number(xs[13])?,
+            turn_around_time: number(xs[14])?,
+            referer: xs[15].to_owned(),
+            user_agent: xs[16].to_owned(),
+            version_id: optional(17),
+            host_id: optional(18),
+            signature_version: optional(19),
+            cipher_suite: optional(20),
+            authentication_type: optional(21),
+            host_header: optional(22),
+            tls_version: optional(23),
+            access_point_arn: optional(24),
+            acl_required: optional(25),
+        };
+        serde_json::to_value(log).map_err(|e| LogError::JsonError(e))
+    }
+}
+
+#[test]
+fn s3_test() {
+    let data = r#"79a59df900b949e55d96a1e698fbacedfd6e09d98eacf8f8d5218e7cd47ef2be DOC-EXAMPLE-BUCKET1 [06/Feb/2019:00:00:38 +0000] 192.0.2.3 79a59df900b949e55d96a1e698fbacedfd6e09d98eacf8f8d5218e7cd47ef2be 3E57427F3EXAMPLE REST.GET.VERSIONING - "GET /DOC-EXAMPLE-BUCKET1?versioning HTTP/1.1" 200 - 113 - 7 - "-" "S3Console/0.4" - s9lzHYrFp76ZVxRcpX9+5cjAnEH2ROuNkd2BHfIa6UkFVdtjf5mKR3/eTPFvsiP/XV/VLi31234= SigV4 ECDHE-RSA-AES128-GCM-SHA256 AuthHeader DOC-EXAMPLE-BUCKET1.s3.us-west-1.amazonaws.com TLSV1.2 arn:aws:s3:us-west-1:123456789012:accesspoint/example-AP Yes"#;
+    let a = S3Parser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["bucket"], "DOC-EXAMPLE-BUCKET1");
+    assert_eq!(a["@timestamp_utc"], "2019-02-06T00:00:38+00:00");
+    assert_eq!(a["operation"], "REST.GET.VERSIONING");
+    assert_eq!(a["method"], "GET");
+    assert_eq!(a["http_status"], 200);
+    assert_eq!(a["bytes_sent"], 113);
+    assert!(a["object_size"].is_null());
+    assert_eq!(a["total_time"], 7);
+    assert_eq!(a["signature_version"], "SigV4");
+    assert_eq!(a["tls_version"], "TLSV1.2");
+    assert_eq!(a["acl_required"], "Yes");
+
+    let data = r#"79a59df900b949e55d96a1e698fbacedfd6e09d98eacf8f8d5218e7cd47ef2be DOC-EXAMPLE-BUCKET1 [06/Feb/2019:00:00:38 +0000] 192.0.2.3 - 3E57427F3EXAMPLE REST.GET.OBJECT photos/cat.jpg "GET /DOC-EXAMPLE-BUCKET1/photos/cat.jpg HTTP/1.1" 404 NoSuchKey 243 - 12 - "-" "curl/7.64.1""#;
+    let a = S3Parser.parse(data.as_bytes()).unwrap();
+    assert_eq!(a["error_code"], "NoSuchKey");
+    assert!(a["host_id"].is_null());
+}
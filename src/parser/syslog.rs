@@ -0,0 +1,225 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{insert_timestamp, LogParser, RecordContext};
+
+const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news",
+    "uucp", "cron", "authpriv", "ftp", "ntp", "security", "console", "solaris-cron",
+    "local0", "local1", "local2", "local3", "local4", "local5", "local6", "local7",
+];
+
+const SEVERITIES: [&str; 8] = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// Syslog in either RFC 5424 or BSD (RFC 3164) form.
+///
+/// `PRI` is decoded into `facility`/`severity` with their names. BSD timestamps have no
+/// year or zone; they are taken as UTC in the year of the record's Firehose arrival
+/// time, or the year before when that would put them more than a day in the future.
+#[derive(Debug)]
+pub struct SyslogParser {
+    rfc5424: Regex,
+    rfc3164: Regex,
+}
+
+impl Default for SyslogParser {
+    fn default() -> SyslogParser {
+        SyslogParser {
+            rfc5424: Regex::new(r"^([1-9]\d?) (\S+) (\S+) (\S+) (\S+) (\S+) ?(.*)$").unwrap(),
+            rfc3164: Regex::new(r"^([A-Z][a-z]{2}) +(\d{1,2}) (\d{2}:\d{2}:\d{2}) (\S+) (?:([^:\[\s]+)(?:\[([^\]]*)\])?: )?(.*)$").unwrap(),
+        }
+    }
+}
+
+impl LogParser for SyslogParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        self.parse_with_context(data, &RecordContext::default())
+    }
+
+    fn parse_with_context(&self, data: &[u8], ctx: &RecordContext) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let (pri, rest) = split_pri(&s)?;
+
+        let mut m = Map::new();
+        if let Some(pri) = pri {
+            let (facility, severity) = (pri / 8, pri % 8);
+            m.insert("facility".to_owned(), Value::from(facility));
+            m.insert("facility_name".to_owned(), Value::from(FACILITIES[facility as usize]));
+            m.insert("severity".to_owned(), Value::from(severity));
+            m.insert("severity_name".to_owned(), Value::from(SEVERITIES[severity as usize]));
+        }
+
+        if let Some(xs) = self.rfc5424.captures(rest) {
+            let nil = |s: &str| if s == "-" { Value::Null } else { Value::from(s) };
+            m.insert("version".to_owned(), Value::from(xs[1].parse::<u8>()?));
+            let time = &xs[2];
+            if time != "-" {
+                insert_timestamp(&mut m, &DateTime::parse_from_rfc3339(time)?);
+            }
+            m.insert("hostname".to_owned(), nil(&xs[3]));
+            m.insert("app_name".to_owned(), nil(&xs[4]));
+            m.insert("procid".to_owned(), nil(&xs[5]));
+            m.insert("msgid".to_owned(), nil(&xs[6]));
+            let (sd, msg) = parse_structured_data(&xs[7])?;
+            m.insert("structured_data".to_owned(), sd);
+            let msg = msg.trim_start_matches(' ').trim_start_matches('\u{feff}');
+            m.insert("message".to_owned(), if msg.is_empty() { Value::Null } else { Value::from(msg) });
+        } else if let Some(xs) = self.rfc3164.captures(rest) {
+            let arrival = ctx.arrival_time.unwrap_or_else(Utc::now);
+            insert_timestamp(&mut m, &bsd_time(&xs[1], &xs[2], &xs[3], arrival)?);
+            m.insert("hostname".to_owned(), Value::from(&xs[4]));
+            m.insert("app_name".to_owned(), xs.get(5).map(|x| Value::from(x.as_str())).unwrap_or(Value::Null));
+            m.insert("procid".to_owned(), xs.get(6).map(|x| Value::from(x.as_str())).unwrap_or(Value::Null));
+            m.insert("message".to_owned(), Value::from(&xs[7]));
+        } else {
+            return Err(LogError::RegexParseError);
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+/// Splits off a leading `<PRI>`, which some relays strip.
+fn split_pri(s: &str) -> Result<(Option<u8>, &str), LogError> {
+    let rest = match s.strip_prefix('<') {
+        Some(rest) => rest,
+        None => return Ok((None, s)),
+    };
+    let end = rest.find('>').ok_or(LogError::RegexParseError)?;
+    let pri = rest[..end].parse::<u8>()?;
+    if pri > 191 {
+        return Err(LogError::RegexParseError);
+    }
+    Ok((Some(pri), &rest[end + 1..]))
+}
+
+fn bsd_time(month: &str, day: &str, time: &str, arrival: DateTime<Utc>) -> Result<DateTime<Utc>, LogError> {
+    let parse = |year: i32| {
+        NaiveDateTime::parse_from_str(&format!("{} {} {:0>2} {}", year, month, day, time), "%Y %b %d %H:%M:%S")
+            .map(|t| Utc.from_utc_datetime(&t))
+    };
+    let t = parse(arrival.year())?;
+    if t > arrival + Duration::days(1) {
+        Ok(parse(arrival.year() - 1)?)
+    } else {
+        Ok(t)
+    }
+}
+
+/// Parses RFC 5424 `STRUCTURED-DATA` into `{"SD-ID": {"PARAM": "value"}}`, returning
+/// it with the rest of the line. Inside values `\"`, `\\` and `\]` are unescaped.
+fn parse_structured_data(s: &str) -> Result<(Value, &str), LogError> {
+    if let Some(rest) = s.strip_prefix('-') {
+        return Ok((Value::Null, rest));
+    }
+
+    let b = s.as_bytes();
+    let mut i = 0;
+    let mut elements = Map::new();
+    while b.get(i) == Some(&b'[') {
+        i += 1;
+        let id_start = i;
+        while i < b.len() && b[i] != b' ' && b[i] != b']' {
+            i += 1;
+        }
+        let id = s[id_start..i].to_owned();
+
+        let mut params = Map::new();
+        loop {
+            while b.get(i) == Some(&b' ') {
+                i += 1;
+            }
+            match b.get(i) {
+                Some(&b']') => {
+                    i += 1;
+                    break;
+                }
+                None => return Err(LogError::RegexParseError),
+                _ => (),
+            }
+
+            let name_start = i;
+            while i < b.len() && b[i] != b'=' {
+                i += 1;
+            }
+            let name = s[name_start..i].to_owned();
+            if s.get(i..i + 2) != Some("=\"") {
+                return Err(LogError::RegexParseError);
+            }
+            i += 2;
+
+            let mut value = String::new();
+            loop {
+                match (b.get(i), b.get(i + 1)) {
+                    (None, _) => return Err(LogError::RegexParseError),
+                    (Some(&b'"'), _) => {
+                        i += 1;
+                        break;
+                    }
+                    (Some(&b'\\'), Some(&c)) if c == b'"' || c == b'\\' || c == b']' => {
+                        value.push(c as char);
+                        i += 2;
+                    }
+                    _ => {
+                        let c = s[i..].chars().next().unwrap();
+                        value.push(c);
+                        i += c.len_utf8();
+                    }
+                }
+            }
+            params.insert(name, Value::from(value));
+        }
+        elements.insert(id, Value::Object(params));
+    }
+    Ok((Value::Object(elements), &s[i..]))
+}
+
+#[test]
+fn rfc5424_test() {
+    let parser = SyslogParser::default();
+    let data = r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Appl\"ication" eventID="1011"][examplePriority@32473 class="high"] An application event log entry..."#;
+    let a = parser.parse(data.as_bytes()).unwrap();
+
+    assert_eq!(a["facility"], 20);
+    assert_eq!(a["facility_name"], "local4");
+    assert_eq!(a["severity"], 5);
+    assert_eq!(a["severity_name"], "notice");
+    assert_eq!(a["version"], 1);
+    assert_eq!(a["@timestamp_utc"], "2003-10-11T22:14:15.003+00:00");
+    assert_eq!(a["hostname"], "mymachine.example.com");
+    assert_eq!(a["app_name"], "evntslog");
+    assert!(a["procid"].is_null());
+    assert_eq!(a["msgid"], "ID47");
+    assert_eq!(a["structured_data"]["exampleSDID@32473"]["eventSource"], "Appl\"ication");
+    assert_eq!(a["structured_data"]["examplePriority@32473"]["class"], "high");
+    assert_eq!(a["message"], "An application event log entry...");
+
+    let a = parser.parse(b"<34>1 - - su - - -").unwrap();
+    assert!(a.get("@timestamp").is_none());
+    assert!(a["structured_data"].is_null());
+    assert!(a["message"].is_null());
+}
+
+#[test]
+fn rfc3164_test() {
+    let parser = SyslogParser::default();
+    let ctx = RecordContext { arrival_time: Some(Utc.with_ymd_and_hms(2018, 1, 1, 0, 0, 5).unwrap()), ..RecordContext::default() };
+    let a = parser.parse_with_context(b"<34>Dec 31 23:59:58 mymachine su[123]: 'su root' failed for lonvick on /dev/pts/8", &ctx).unwrap();
+
+    assert_eq!(a["facility_name"], "auth");
+    assert_eq!(a["severity_name"], "crit");
+    assert_eq!(a["@timestamp_utc"], "2017-12-31T23:59:58+00:00");
+    assert_eq!(a["hostname"], "mymachine");
+    assert_eq!(a["app_name"], "su");
+    assert_eq!(a["procid"], "123");
+    assert_eq!(a["message"], "'su root' failed for lonvick on /dev/pts/8");
+
+    let a = parser.parse_with_context(b"Jan  1 00:00:01 host kernel: eth0 up", &ctx).unwrap();
+    assert_eq!(a["@timestamp_utc"], "2018-01-01T00:00:01+00:00");
+    assert!(a.get("facility").is_none());
+
+    assert!(parser.parse(b"<999>1 - - - - - -").is_err());
+    assert!(parser.parse(b"not syslog").is_err());
+}
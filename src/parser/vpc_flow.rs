@@ -0,0 +1,175 @@
+use serde_json::{Map, Value};
+
+use error::LogError;
+use super::{from_epoch, insert_timestamp, LogParser};
+use super::fields::int;
+
+/// The default (version 2) flow log format.
+pub const DEFAULT_FORMAT: &str = "${version} ${account-id} ${interface-id} ${srcaddr} ${dstaddr} \
+    ${srcport} ${dstport} ${protocol} ${packets} ${bytes} ${start} ${end} ${action} ${log-status}";
+
+/// Fields describing the traffic of a flow, which `NODATA` and `SKIPDATA` entries don't have.
+const TRAFFIC_FIELDS: [&str; 16] = [
+    "srcaddr", "dstaddr", "srcport", "dstport", "protocol", "packets", "bytes", "action",
+    "tcp_flags", "type", "pkt_srcaddr", "pkt_dstaddr", "pkt_src_aws_service", "pkt_dst_aws_service",
+    "flow_direction", "traffic_path",
+];
+
+/// IANA names of the protocols flow logs commonly carry.
+fn protocol_name(n: i64) -> Option<&'static str> {
+    match n {
+        1 => Some("ICMP"),
+        2 => Some("IGMP"),
+        6 => Some("TCP"),
+        17 => Some("UDP"),
+        41 => Some("IPv6"),
+        47 => Some("GRE"),
+        50 => Some("ESP"),
+        51 => Some("AH"),
+        58 => Some("ICMPv6"),
+        132 => Some("SCTP"),
+        _ => None,
+    }
+}
+
+/// Amazon VPC Flow Logs, versions 2 to 5, in the field order given by `VPC_FLOW_LOG_FORMAT`.
+///
+/// Field names are snake cased. `start`/`end` become RFC3339 `start`/`end` plus the
+/// `@timestamp` pair taken from `start`, and `protocol` gains a `protocol_name`.
+/// Entries whose `log_status` is `NODATA` (no traffic in the window) or `SKIPDATA`
+/// (records skipped) have null for every traffic field, such as addresses, ports,
+/// `protocol`, `bytes` and `action`, whatever the line holds.
+#[derive(Debug)]
+pub struct VpcFlowParser {
+    /// Field names as written in the format, e.g. `account-id`.
+    names: Vec<String>,
+    fields: Vec<String>,
+}
+
+impl Default for VpcFlowParser {
+    fn default() -> VpcFlowParser {
+        VpcFlowParser::new(DEFAULT_FORMAT).unwrap()
+    }
+}
+
+impl VpcFlowParser {
+    pub fn from_env() -> Result<VpcFlowParser, LogError> {
+        match std::env::var("VPC_FLOW_LOG_FORMAT") {
+            Ok(format) => VpcFlowParser::new(&format),
+            Err(_) => Ok(VpcFlowParser::default()),
+        }
+    }
+
+    pub fn new(format: &str) -> Result<VpcFlowParser, LogError> {
+        let names = format.split_whitespace()
+            .map(|f| {
+                f.strip_prefix("${").and_then(|f| f.strip_suffix('}'))
+                    .map(|f| f.to_owned())
+                    .ok_or_else(|| LogError::ConfigError(format!("invalid flow log field: {}", f)))
+            })
+            .collect::<Result<Vec<String>, LogError>>()?;
+        let fields = names.iter().map(|f| f.replace('-', "_")).collect();
+        Ok(VpcFlowParser { names, fields })
+    }
+}
+
+impl LogParser for VpcFlowParser {
+    fn parse(&self, data: &[u8]) -> Result<Value, LogError> {
+        let s = String::from_utf8(data.to_vec())?;
+        let xs = s.split_whitespace().collect::<Vec<&str>>();
+        if xs.len() != self.fields.len() {
+            return Err(LogError::RegexParseError);
+        }
+        // The header line of flow logs delivered to S3 repeats the field names.
+        if self.names.iter().zip(&xs).all(|(name, x)| name == x) {
+            return Ok(Value::Null);
+        }
+
+        let log_status = self.fields.iter().position(|f| f == "log_status").map(|i| xs[i]);
+        let has_traffic = !matches!(log_status, Some("NODATA") | Some("SKIPDATA"));
+
+        let mut m = Map::new();
+        for (name, &x) in self.fields.iter().zip(&xs) {
+            if !has_traffic && TRAFFIC_FIELDS.contains(&name.as_str()) {
+                if name == "protocol" {
+                    m.insert("protocol_name".to_owned(), Value::Null);
+                }
+                m.insert(name.clone(), Value::Null);
+                continue;
+            }
+            let v = match name.as_str() {
+                "version" | "srcport" | "dstport" | "packets" | "bytes" | "tcp_flags"
+                | "traffic_path" => int(x)?,
+                "protocol" => {
+                    let n = int(x)?;
+                    let protocol_name = n.as_i64().and_then(protocol_name).map(Value::from);
+                    m.insert("protocol_name".to_owned(), protocol_name.unwrap_or(Value::Null));
+                    n
+                }
+                "start" | "end" => match int(x)?.as_i64() {
+                    Some(secs) => {
+                        let time = from_epoch(secs, 0)?;
+                        if name == "start" {
+                            insert_timestamp(&mut m, &time);
+                        }
+                        Value::from(time.to_rfc3339())
+                    }
+                    None => Value::Null,
+                },
+                _ if x == "-" => Value::Null,
+                _ => Value::from(x),
+            };
+            m.insert(name.clone(), v);
+        }
+        Ok(Value::Object(m))
+    }
+}
+
+#[test]
+fn vpc_flow_test() {
+    let parser = VpcFlowParser::default();
+    let a = parser.parse(b"2 123456789010 eni-1235b8ca123456789 172.31.16.139 172.31.16.21 20641 22 6 20 4249 1418530010 1418530070 ACCEPT OK").unwrap();
+    assert_eq!(a["account_id"], "123456789010");
+    assert_eq!(a["dstport"], 22);
+    assert_eq!(a["protocol"], 6);
+    assert_eq!(a["protocol_name"], "TCP");
+    assert_eq!(a["bytes"], 4249);
+    assert_eq!(a["start"], "2014-12-14T04:06:50+00:00");
+    assert_eq!(a["@timestamp_utc"], "2014-12-14T04:06:50+00:00");
+    assert_eq!(a["action"], "ACCEPT");
+
+    let a = parser.parse(b"2 123456789010 eni-1235b8ca123456789 - - - - - - - 1431280876 1431280934 - NODATA").unwrap();
+    assert_eq!(a["log_status"], "NODATA");
+    assert!(a["srcaddr"].is_null());
+    assert!(a["protocol_name"].is_null());
+    assert_eq!(a["start"], "2015-05-10T18:01:16+00:00");
+
+    let a = parser.parse(b"2 123456789010 eni-1235b8ca123456789 - - 0 0 0 0 0 1431280876 1431280934 - SKIPDATA").unwrap();
+    assert_eq!(a["log_status"], "SKIPDATA");
+    assert!(a["srcport"].is_null());
+    assert!(a["bytes"].is_null());
+    assert!(a["protocol"].is_null());
+    assert_eq!(a["interface_id"], "eni-1235b8ca123456789");
+
+    assert!(parser.parse(b"version account-id interface-id srcaddr dstaddr srcport dstport protocol packets bytes start end action log-status").unwrap().is_null());
+}
+
+#[test]
+fn custom_format_test() {
+    let parser = VpcFlowParser::new("${version} ${vpc-id} ${srcaddr} ${pkt-srcaddr} ${tcp-flags} ${type} ${flow-direction} ${start} ${log-status}").unwrap();
+    let a = parser.parse(b"5 vpc-abcdefab012345678 10.0.1.5 10.20.33.164 19 IPv4 ingress 1566848875 OK").unwrap();
+    assert_eq!(a["vpc_id"], "vpc-abcdefab012345678");
+    assert_eq!(a["pkt_srcaddr"], "10.20.33.164");
+    assert_eq!(a["tcp_flags"], 19);
+    assert_eq!(a["flow_direction"], "ingress");
+
+    let parser = VpcFlowParser::new("${version} ${sublocation-type} ${sublocation-id} ${log-status}").unwrap();
+    let a = parser.parse(b"5 outpost op-0123456789abcdef0 OK").unwrap();
+    assert_eq!(a["sublocation_type"], "outpost");
+    assert_eq!(a["sublocation_id"], "op-0123456789abcdef0");
+
+    assert!(parser.parse(b"version sublocation-type sublocation-id log-status").unwrap().is_null());
+    assert!(parser.parse(b"version outpost op-0123456789abcdef0 OK").is_err());
+
+    assert!(VpcFlowParser::new("${version} account-id").is_err());
+}
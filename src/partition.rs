@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use chrono::prelude::*;
+use serde_json::Value;
+
+use error::LogError;
+
+/// How the value of a partition key is derived from a parsed field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartitionValue {
+    /// The field as is.
+    Field,
+    /// An RFC3339 timestamp field formatted with a strftime pattern, e.g. `%Y`.
+    Time(String),
+    /// An HTTP status field reduced to its class, e.g. `404` to `4xx`.
+    StatusClass,
+}
+
+/// One `metadata.partitionKeys` entry for Firehose dynamic partitioning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionKey {
+    pub name: String,
+    pub field: String,
+    pub value: PartitionValue,
+}
+
+/// `metadata` of a Firehose response record.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    pub partition_keys: BTreeMap<String, String>,
+}
+
+/// Parses `PARTITION_KEYS`, a comma separated list of `name=field[:modifier]` where the
+/// modifier is either `class` or a strftime pattern, e.g.
+/// `year=@timestamp_utc:%Y,month=@timestamp_utc:%m,status=response:class`.
+pub fn parse_partition_keys(s: &str) -> Result<Vec<PartitionKey>, LogError> {
+    s.split(',')
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| {
+            let err = || LogError::ConfigError(format!("invalid partition key: {}", x));
+            let mut kv = x.trim().splitn(2, '=');
+            let name = kv.next().filter(|n| !n.is_empty()).ok_or_else(err)?;
+            let mut spec = kv.next().ok_or_else(err)?.splitn(2, ':');
+            let field = spec.next().filter(|f| !f.is_empty()).ok_or_else(err)?;
+            let value = match spec.next() {
+                None => PartitionValue::Field,
+                Some("class") => PartitionValue::StatusClass,
+                Some(f) if f.contains('%') => PartitionValue::Time(f.to_owned()),
+                Some(_) => return Err(err()),
+            };
+            Ok(PartitionKey { name: name.to_owned(), field: field.to_owned(), value })
+        })
+        .collect()
+}
+
+/// Partition keys of a record. Firehose delivers a record under a single prefix, so
+/// every parsed line must agree on each key; a line without a usable value for a key
+/// leaves it to the others, and the key fails only when no line has one.
+pub fn metadata(keys: &[PartitionKey], lines: &[Value]) -> Result<Option<Metadata>, LogError> {
+    if keys.is_empty() || lines.is_empty() {
+        return Ok(None);
+    }
+
+    let mut partition_keys = BTreeMap::new();
+    let mut conflicts = Vec::new();
+    for key in keys {
+        let mut values = lines.iter().filter_map(|line| value(key, line));
+        let first = values.next().ok_or_else(|| LogError::PartitionKeyError(key.field.clone()))?;
+        if values.any(|v| v != first) {
+            conflicts.push(key.name.clone());
+        }
+        partition_keys.insert(key.name.clone(), first);
+    }
+    if !conflicts.is_empty() {
+        return Err(LogError::PartitionKeyConflict(conflicts));
+    }
+    Ok(Some(Metadata { partition_keys }))
+}
+
+/// The value of `key` for one line, or `None` when its field is missing or unusable.
+fn value(key: &PartitionKey, line: &Value) -> Option<String> {
+    let field = match *line.get(&key.field)? {
+        Value::String(ref s) => s.clone(),
+        Value::Number(ref n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => return None,
+    };
+    match key.value {
+        PartitionValue::Field => Some(field),
+        PartitionValue::Time(ref f) => DateTime::parse_from_rfc3339(&field).ok().map(|t| t.format(f).to_string()),
+        PartitionValue::StatusClass => match field.chars().next() {
+            Some(c) if field.len() == 3 && c.is_ascii_digit() => Some(format!("{}xx", c)),
+            _ => None,
+        },
+    }
+}
+
+#[test]
+fn parse_partition_keys_test() {
+    let keys = parse_partition_keys("year=@timestamp_utc:%Y, status=response:class,host=host").unwrap();
+    assert_eq!(keys.len(), 3);
+    assert_eq!(keys[0].value, PartitionValue::Time("%Y".to_owned()));
+    assert_eq!(keys[1].field, "response");
+    assert_eq!(keys[2].value, PartitionValue::Field);
+
+    assert!(parse_partition_keys("year").is_err());
+    assert!(parse_partition_keys("year=@timestamp_utc:yyyy").is_err());
+}
+
+#[test]
+fn metadata_test() {
+    let keys = parse_partition_keys("year=@timestamp_utc:%Y,hour=@timestamp_utc:%H,status=response:class").unwrap();
+    let line = serde_json::from_str::<Value>(r#"{"@timestamp_utc":"2017-12-14T13:16:45+00:00","response":404}"#).unwrap();
+    let m = metadata(&keys, &[line.clone()]).unwrap().unwrap();
+
+    assert_eq!(m.partition_keys["year"], "2017");
+    assert_eq!(m.partition_keys["hour"], "13");
+    assert_eq!(m.partition_keys["status"], "4xx");
+
+    assert!(metadata(&[], &[line.clone()]).unwrap().is_none());
+    assert!(metadata(&keys, &[]).unwrap().is_none());
+    let keys = parse_partition_keys("vhost=vhost").unwrap();
+    assert!(metadata(&keys, &[line]).is_err());
+}
+
+#[test]
+fn metadata_mixed_lines_test() {
+    let keys = parse_partition_keys("year=@timestamp_utc:%Y,status=response:class,vhost=vhost").unwrap();
+    let lines = serde_json::from_str::<Vec<Value>>(r#"[
+        {"@timestamp_utc":"2017-12-14T13:16:45+00:00","response":404},
+        {"@timestamp_utc":"2017-12-14T13:16:46+00:00","response":403,"vhost":"example.com"}]"#).unwrap();
+    let m = metadata(&keys, &lines).unwrap().unwrap();
+
+    assert_eq!(m.partition_keys["year"], "2017");
+    assert_eq!(m.partition_keys["status"], "4xx");
+    assert_eq!(m.partition_keys["vhost"], "example.com");
+
+    let lines = serde_json::from_str::<Vec<Value>>(r#"[
+        {"@timestamp_utc":"2017-12-31T23:59:59+00:00","response":200,"vhost":"example.com"},
+        {"@timestamp_utc":"2018-01-01T00:00:00+00:00","response":500,"vhost":"example.com"}]"#).unwrap();
+    match metadata(&keys, &lines) {
+        Err(LogError::PartitionKeyConflict(ref conflicts)) => assert_eq!(conflicts, &["year", "status"]),
+        r => panic!("unexpected {:?}", r),
+    }
+}
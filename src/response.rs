@@ -0,0 +1,18 @@
+use lambda::event::firehose::KinesisFirehoseResponseRecord;
+
+use partition::Metadata;
+
+/// Firehose transformation response. Unlike `KinesisFirehoseResponse` its records can carry
+/// `metadata` for dynamic partitioning.
+#[derive(Serialize)]
+pub struct FirehoseResponse {
+    pub records: Vec<FirehoseResponseRecord>,
+}
+
+#[derive(Serialize)]
+pub struct FirehoseResponseRecord {
+    #[serde(flatten)]
+    pub record: KinesisFirehoseResponseRecord,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+}